@@ -1,43 +1,603 @@
-use chrono::Utc;
-use clap::{Parser, Subcommand};
+use chrono::format::StrftimeItems;
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 use colored::*;
-use dialoguer::Select;
-use notify::{Event, EventKind, RecursiveMode, Watcher, recommended_watcher};
+use dialoguer::{Confirm, FuzzySelect};
+use glob::{Pattern, glob};
+use ignore::WalkBuilder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::event::{DataChange, ModifyKind, RenameMode};
+use notify::{Event, EventKind, PollWatcher, RecursiveMode, Watcher, recommended_watcher};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use shell_words::split;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{self, Child, Command};
-use std::sync::mpsc;
-use std::thread;
+use std::process::{self, Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Once, OnceLock};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use terminal_size::{Width, terminal_size};
-use walkdir::WalkDir;
+use terminal_size::{Height, Width, terminal_size};
+use toml_edit::{DocumentMut, Item, Table, TableLike, value};
 
 const CUE: &str = "[cue]";
 const DEBOUNCE_MS: u64 = 150;
+const KILL_TIMEOUT_MS: u64 = 2000;
+const POLL_INTERVAL_MS: u64 = 500;
+const CHILD_POLL_MS: u64 = 50;
+const RETRY_BASE_MS: u64 = 500;
+const STORM_WINDOW_MS: u64 = 10_000;
+const STORM_RUNS: usize = 5;
+const SELF_TRIGGER_RUNS: usize = 3;
+const CONFIG_FILES: &[&str] = &["cue.toml", "cue.yaml", "cue.yml", "cue.json"];
+const DEFAULT_IGNORE: &[&str] = &[".git", "target", "node_modules", "dist", "build"];
+// swap, backup and lock files editors write next to the file being edited
+const EDITOR_TEMP_FILES: &[&str] = &[
+    "*.swp",
+    "*.swo",
+    "*.swx",
+    "*~",
+    "4913",
+    "#*#",
+    ".#*",
+    "*___jb_tmp___",
+    "*___jb_old___",
+];
+
+static LOG_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+// with --json-events stdout carries only the event stream, so the child's
+// output is sent to stderr instead
+static JSON_EVENTS: AtomicBool = AtomicBool::new(false);
+static PID_FILE: OnceLock<PathBuf> = OnceLock::new();
+// --until pattern, checked against every line of the child's output
+static UNTIL: OnceLock<Regex> = OnceLock::new();
+static UNTIL_MATCHED: AtomicBool = AtomicBool::new(false);
+// set by Ctrl-C or `cue stop` during --once, which has no watcher loop to
+// stop the command; whatever is waiting on it stops it instead
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// every watcher's current run, so Ctrl-C can stop all of them
+static RUNNING: Mutex<Vec<Arc<Mutex<Option<Run>>>>> = Mutex::new(Vec::new());
+// totals across every watcher, summed up when cue is stopped
+static SESSION: Mutex<Session> = Mutex::new(Session {
+    passed: 0,
+    failed: 0,
+    killed: 0,
+    busy: Duration::ZERO,
+    quiet: true,
+});
+// every watcher loop's channel, so one line read from stdin reaches every task
+static STDIN_LISTENERS: Mutex<Vec<mpsc::Sender<Message>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // what cue's own lines start with: [cue], or [cue:<label>] with --label
+    // and in each of several tasks running side by side, in the task's color
+    static TAG: RefCell<(String, Color)> = RefCell::new((CUE.to_string(), Color::Green));
+}
+
+fn set_tag(label: Option<&str>) {
+    TAG.with_borrow_mut(|(tag, _)| {
+        *tag = label.map_or_else(|| CUE.to_string(), |l| format!("[cue:{}]", l));
+    });
+}
+
+fn set_tag_color(color: Color) {
+    TAG.with_borrow_mut(|(_, c)| *c = color);
+}
+
+fn tag() -> ColoredString {
+    TAG.with_borrow(|(tag, color)| tag.color(*color))
+}
 
 macro_rules! log {
-    ($quiet:expr, $($arg:tt)*) => {
+    ($quiet:expr, $($arg:tt)*) => {{
+        let line = format!($($arg)*);
         if !$quiet {
-            println!($($arg)*);
+            println!("{}", line);
+        }
+        write_log(&line);
+    }};
+}
+
+macro_rules! debug {
+    ($verbose:expr, $($arg:tt)*) => {
+        if $verbose > 0 {
+            let line = format!("{} {}", tag().dimmed(), format!($($arg)*).dimmed());
+            // stdout carries nothing but events with --json-events
+            if JSON_EVENTS.load(Ordering::Relaxed) {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+    };
+}
+
+fn setup_output(opts: &mut WatchOptions) {
+    set_tag(opts.label.as_deref());
+    if let Some(path) = &opts.log_file {
+        open_log_file(path);
+    }
+    if opts.json_events {
+        opts.quiet = true;
+        opts.no_clear = true;
+        JSON_EVENTS.store(true, Ordering::Relaxed);
+    }
+    // relative to where cue was started, not the config's directory it
+    // moves into later
+    if let Some(path) = &mut opts.pid_file
+        && let Ok(absolute) = std::path::absolute(&*path)
+    {
+        *path = absolute;
+    }
+    if let Some(pattern) = &opts.until {
+        let re = Regex::new(pattern).unwrap_or_else(|e| {
+            eprintln!("{} invalid --until pattern: {}", "Error:".red(), e);
+            finish(1);
+        });
+        UNTIL.set(re).ok();
+    }
+}
+
+// written only once the command is about to run, so none of the earlier
+// error exits leave the file behind
+fn write_pid_file(opts: &WatchOptions) {
+    let Some(path) = &opts.pid_file else {
+        return;
+    };
+    if PID_FILE.get().is_some() {
+        return;
+    }
+    fs::write(path, process::id().to_string()).unwrap_or_else(|e| {
+        eprintln!(
+            "{} failed to write pid file '{}': {}",
+            "Error:".red(),
+            path.display(),
+            e
+        );
+        finish(1);
+    });
+    PID_FILE.set(path.clone()).ok();
+}
+
+// every exit goes through here, so the pid file is removed if cue wrote one
+fn finish(code: i32) -> ! {
+    if let Some(path) = PID_FILE.get() {
+        fs::remove_file(path).ok();
+    }
+    process::exit(code);
+}
+
+// an error from a run or the watcher, reported without leaving the pid file
+fn or_finish<T>(result: Result<T, Box<dyn std::error::Error>>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{} {}", "Error:".red(), e);
+        finish(1);
+    })
+}
+
+fn stop_running(pid_file: &Path) {
+    let pid: u32 = fs::read_to_string(pid_file)
+        .ok()
+        .and_then(|content| content.trim().parse().ok())
+        .unwrap_or_else(|| {
+            eprintln!(
+                "{} no valid pid in '{}' — is cue running?",
+                "Error:".red(),
+                pid_file.display()
+            );
+            finish(1);
+        });
+    #[cfg(unix)]
+    let stopped = {
+        use nix::errno::Errno;
+        use nix::sys::signal::{Signal, kill};
+        use nix::unistd::Pid;
+
+        match is_cue(pid).then(|| kill(Pid::from_raw(pid as i32), Signal::SIGTERM)) {
+            None | Some(Err(Errno::ESRCH)) => false,
+            Some(Ok(())) => true,
+            Some(Err(e)) => {
+                eprintln!("{} failed to stop cue ({}): {}", "Error:".red(), pid, e);
+                finish(1);
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let stopped = Command::new("taskkill")
+        .args(["/PID", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+
+    if stopped {
+        println!("{} stopped cue ({})", tag(), pid);
+    } else {
+        fs::remove_file(pid_file).ok();
+        println!(
+            "{} cue ({}) isn't running — removed the stale pid file",
+            tag(),
+            pid
+        );
+    }
+}
+
+// a pid file left behind by a crash may name a pid that has since been
+// reused, so only a process running cue's own binary counts as cue
+#[cfg(unix)]
+fn is_cue(pid: u32) -> bool {
+    let Some(name) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_name().map(|n| n.to_string_lossy().to_string()))
+    else {
+        return true;
+    };
+    let running = fs::read_link(format!("/proc/{}/exe", pid))
+        .ok()
+        .map(|exe| exe.to_string_lossy().to_string())
+        .or_else(|| {
+            Command::new("ps")
+                .args(["-p", &pid.to_string(), "-o", "comm="])
+                .output()
+                .ok()
+                .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        })
+        .unwrap_or_default();
+    // a binary replaced while it runs, as by a rebuild, shows up as deleted
+    let running = running.trim_end_matches(" (deleted)");
+    Path::new(running).file_name().is_some_and(|n| *n == *name)
+}
+
+// starts cue again, detached, with the same arguments and its output going
+// to the log file, then returns to the shell
+fn daemonize(log_file: Option<&Path>, invoked_from: Option<PathBuf>) -> ! {
+    let Some(log_file) = log_file else {
+        eprintln!(
+            "{} --daemon needs --log-file — output would be lost otherwise",
+            "Error:".red()
+        );
+        finish(1);
+    };
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "{} failed to open log file '{}': {}",
+                "Error:".red(),
+                log_file.display(),
+                e
+            );
+            finish(1);
+        });
+    match spawn_detached(log, invoked_from) {
+        Ok(child) => {
+            println!(
+                "{} running in the background ({}) — logging to '{}'",
+                tag(),
+                child.id(),
+                log_file.display()
+            );
+            finish(0);
+        }
+        Err(e) => {
+            eprintln!(
+                "{} failed to start in the background: {}",
+                "Error:".red(),
+                e
+            );
+            finish(1);
+        }
+    }
+}
+
+fn spawn_detached(log: File, invoked_from: Option<PathBuf>) -> std::io::Result<Child> {
+    // --quiet keeps status lines from reaching the log twice, once through
+    // stdout and once through --log-file, and --no-clear keeps escape codes
+    // out of it
+    // anything after `--` belongs to the command and is passed on untouched
+    let mut passthrough = false;
+    let args = std::env::args_os().skip(1).flat_map(move |a| {
+        passthrough |= a == "--";
+        if a == "--daemon" && !passthrough {
+            vec!["--quiet".into(), "--no-clear".into()]
+        } else {
+            vec![a]
         }
+    });
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    if let Some(dir) = invoked_from {
+        cmd.current_dir(dir);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // a session of its own, so it has no controlling terminal: closing
+        // this one doesn't SIGHUP it, and Ctrl-C and job control here don't
+        // reach it. setsid also makes it a group leader, so no
+        // process_group(0), which would make setsid fail
+        // SAFETY: setsid is async-signal-safe and touches nothing of ours
+        unsafe {
+            cmd.pre_exec(|| {
+                nix::unistd::setsid()?;
+                Ok(())
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x8;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x200;
+        cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+    cmd.spawn()
+}
+
+fn open_log_file(path: &Path) {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "{} failed to open log file '{}': {}",
+                "Error:".red(),
+                path.display(),
+                e
+            );
+            finish(1);
+        });
+    LOG_FILE.set(Mutex::new(file)).ok();
+}
+
+// appends a timestamped, color-free copy of the line to --log-file
+fn write_log(line: &str) {
+    let Some(file) = LOG_FILE.get() else {
+        return;
     };
+    let line = strip_ansi(line);
+    if let Ok(mut file) = file.lock() {
+        writeln!(
+            file,
+            "{} {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            line
+        )
+        .ok();
+    }
+}
+
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip the CSI sequence up to its final letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
 }
 
 #[derive(Serialize, Deserialize, Default)]
 struct CueConfig {
-    default: Option<String>,
-    tasks: HashMap<String, Task>,
+    default: Option<DefaultTask>,
+    defaults: Option<Defaults>,
+    tasks: BTreeMap<String, Task>,
+}
+
+impl CueConfig {
+    // the task with whatever it leaves unset filled in from [defaults]
+    fn task(&self, name: &str) -> Option<Task> {
+        let task = self.tasks.get(name)?.clone();
+        Some(match &self.defaults {
+            Some(defaults) => task.with_defaults(defaults),
+            None => task,
+        })
+    }
+
+    // every task with [defaults] already applied, so they stay with the
+    // tasks of this file once another file's tasks are mixed in
+    fn into_resolved_tasks(self) -> BTreeMap<String, Task> {
+        let defaults = self.defaults;
+        self.tasks
+            .into_iter()
+            .map(|(name, task)| match &defaults {
+                Some(defaults) => (name, task.with_defaults(defaults)),
+                None => (name, task),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Defaults {
+    debounce: Option<u64>,
+    quiet: Option<bool>,
+    ignore: Option<Vec<String>>,
+    extensions: Option<Vec<String>>,
+    env: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum DefaultTask {
+    Single(String),
+    Several(Vec<String>),
+}
+
+impl DefaultTask {
+    fn names(&self) -> Vec<String> {
+        match self {
+            DefaultTask::Single(name) => vec![name.clone()],
+            DefaultTask::Several(names) => names.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum RunCommand {
+    Single(String),
+    Sequence(Vec<String>),
+}
+
+impl RunCommand {
+    fn steps(&self) -> Vec<String> {
+        match self {
+            RunCommand::Single(run) => vec![run.clone()],
+            RunCommand::Sequence(runs) => runs.clone(),
+        }
+    }
+}
+
+impl From<Vec<String>> for RunCommand {
+    fn from(mut runs: Vec<String>) -> Self {
+        if runs.len() == 1 {
+            RunCommand::Single(runs.remove(0))
+        } else {
+            RunCommand::Sequence(runs)
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 struct Task {
+    description: Option<String>,
+    #[serde(default)]
     watch: Vec<String>,
-    run: Option<String>,
+    watch_shallow: Option<Vec<String>>,
+    run: Option<RunCommand>,
+    cmd: Option<String>,
+    args: Option<Vec<String>>,
     extensions: Option<Vec<String>>,
+    shell: Option<bool>,
+    ignore: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    run_on_start: Option<bool>,
+    before: Option<RunCommand>,
+    after: Option<RunCommand>,
+    env_file: Option<PathBuf>,
+    env: Option<BTreeMap<String, String>>,
+    timestamp: Option<Timestamp>,
+    timeout: Option<u64>,
+    debounce: Option<u64>,
+    quiet: Option<bool>,
+    no_clear: Option<bool>,
+    deps: Option<Vec<String>>,
+    color: Option<String>,
+    // the file the task was loaded from, which keys its --track results
+    #[serde(skip)]
+    source: Option<PathBuf>,
+}
+
+impl Task {
+    fn with_defaults(mut self, defaults: &Defaults) -> Task {
+        self.debounce = self.debounce.or(defaults.debounce);
+        self.quiet = self.quiet.or(defaults.quiet);
+        self.ignore = self.ignore.or_else(|| defaults.ignore.clone());
+        self.extensions = self.extensions.or_else(|| defaults.extensions.clone());
+        // the task's own variables win key by key
+        if let Some(env) = &defaults.env {
+            let mut merged = env.clone();
+            merged.extend(self.env.unwrap_or_default());
+            self.env = Some(merged);
+        }
+        self
+    }
+
+    // `run`, or `cmd` and `args` joined into a single step for display
+    fn runs(&self) -> Option<Vec<String>> {
+        self.run.as_ref().map(RunCommand::steps).or_else(|| {
+            self.cmd.as_ref().map(|cmd| {
+                vec![shell_words::join(
+                    std::iter::once(cmd).chain(self.args.iter().flatten()),
+                )]
+            })
+        })
+    }
+
+    // `cmd` and `args` are used as given, without going through shell_words
+    fn apply_exact_command(&self, steps: &mut [Step]) {
+        let (None, Some(cmd)) = (&self.run, &self.cmd) else {
+            return;
+        };
+        for step in steps.iter_mut().filter(|s| s.stage == Stage::Main) {
+            step.command.cmd = cmd.clone();
+            step.command.args = self.args.clone().unwrap_or_default();
+            step.command.shell = false;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Timestamp {
+    Local,
+    Utc,
+    Off,
+}
+
+// durations on the command line: `500ms`, `1s`, `1.5s`, `2m`, `1h`, or a
+// bare number of milliseconds
+fn parse_millis(s: &str) -> Result<u64, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("expected a duration like 500ms, 1s or 2m, got '{}'", s))?;
+    let scale = match unit.trim() {
+        "" | "ms" => 1.0,
+        "s" => 1000.0,
+        "m" => 60_000.0,
+        "h" => 3_600_000.0,
+        _ => return Err(format!("unknown unit in '{}' — use ms, s, m or h", s)),
+    };
+    Ok((number * scale).round() as u64)
+}
+
+#[derive(Clone, Copy)]
+enum SeparatorWidth {
+    Full,
+    Half,
+    Columns(usize),
+}
+
+impl std::str::FromStr for SeparatorWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(SeparatorWidth::Full),
+            "half" => Ok(SeparatorWidth::Half),
+            n => n.parse().map(SeparatorWidth::Columns).map_err(|_| {
+                format!(
+                    "expected 'full', 'half' or a number of columns, got '{}'",
+                    n
+                )
+            }),
+        }
+    }
 }
 
 #[derive(Parser)]
@@ -51,18 +611,167 @@ struct Cli {
     command: Option<Commands>,
     #[arg(short, long, num_args = 1..)]
     watch: Vec<String>,
-    #[arg(short, long)]
-    run: Option<String>,
+    #[arg(short, long, num_args = 1, action = clap::ArgAction::Append)]
+    run: Option<Vec<String>>,
     #[arg(short, long, num_args = 1..)]
     extensions: Option<Vec<String>>,
-    #[arg(long, short, default_value_t = DEBOUNCE_MS)]
-    debounce: u64,
     #[arg(long, short)]
     global: bool,
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[arg(long, global = true)]
+    no_merge: bool,
+    #[arg(long, global = true, value_enum, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+    #[arg(short = 'C', long, global = true)]
+    working_dir: Option<PathBuf>,
+    #[command(flatten)]
+    opts: WatchOptions,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+enum TriggerEvent {
+    Modify,
+    Create,
+    Remove,
+    Rename,
+    Access,
+}
+
+impl TriggerEvent {
+    // notify reports renames as a kind of modify event, so `modify` covers them too
+    fn matches(self, kind: &EventKind) -> bool {
+        match self {
+            TriggerEvent::Modify => matches!(kind, EventKind::Modify(_)),
+            TriggerEvent::Create => matches!(kind, EventKind::Create(_)),
+            TriggerEvent::Remove => matches!(kind, EventKind::Remove(_)),
+            TriggerEvent::Rename => matches!(kind, EventKind::Modify(ModifyKind::Name(_))),
+            TriggerEvent::Access => matches!(kind, EventKind::Access(_)),
+        }
+    }
+}
+
+#[derive(Args, Clone)]
+struct WatchOptions {
+    #[arg(long, short, visible_alias = "settle", value_parser = parse_millis)]
+    debounce: Option<u64>,
     #[arg(long, short)]
     quiet: bool,
     #[arg(long, short)]
     no_clear: bool,
+    #[arg(long, default_value_t = KILL_TIMEOUT_MS, value_parser = parse_millis)]
+    kill_timeout: u64,
+    #[arg(long)]
+    shell: bool,
+    #[arg(long, num_args = 1..)]
+    ignore: Option<Vec<String>>,
+    #[arg(long)]
+    no_gitignore: bool,
+    #[arg(long)]
+    enumerate: bool,
+    #[arg(long)]
+    follow_symlinks: bool,
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_depth: Option<u32>,
+    #[arg(long)]
+    exclude: Vec<String>,
+    #[arg(long)]
+    skip_unchanged: bool,
+    #[arg(long)]
+    poll: bool,
+    #[arg(long, default_value_t = POLL_INTERVAL_MS, value_parser = parse_millis)]
+    poll_interval: u64,
+    #[arg(long)]
+    once: bool,
+    #[arg(long)]
+    no_initial: bool,
+    #[arg(long, conflicts_with = "once")]
+    watch_only: bool,
+    #[arg(long)]
+    keep_going: bool,
+    #[arg(long)]
+    env_file: Option<PathBuf>,
+    #[arg(long)]
+    wait: bool,
+    #[arg(long, value_enum)]
+    timestamp: Option<Timestamp>,
+    #[arg(long, default_value = "%H:%M:%S")]
+    time_format: String,
+    #[arg(long, default_value_t = '_')]
+    separator: char,
+    #[arg(long, default_value = "half")]
+    separator_width: SeparatorWidth,
+    #[arg(long)]
+    notify: bool,
+    #[arg(long)]
+    bell: bool,
+    #[arg(long)]
+    prefix: bool,
+    #[arg(long)]
+    until: Option<String>,
+    #[arg(long, default_value_t = 0)]
+    retry: u32,
+    #[arg(long)]
+    queue: bool,
+    #[arg(long, value_parser = parse_millis)]
+    timeout: Option<u64>,
+    #[arg(long)]
+    dry_run: bool,
+    #[arg(long, value_parser = parse_millis)]
+    min_interval: Option<u64>,
+    #[arg(long)]
+    scroll: bool,
+    #[arg(long)]
+    json_events: bool,
+    #[arg(long)]
+    pid_file: Option<PathBuf>,
+    #[arg(long)]
+    debounce_per_path: bool,
+    #[arg(long, value_parser = parse_millis)]
+    max_wait: Option<u64>,
+    #[arg(long)]
+    fail_limit: Option<u32>,
+    #[arg(long)]
+    no_default_ignores: bool,
+    #[arg(long, value_enum, value_delimiter = ',', default_values = ["modify", "create"])]
+    events: Vec<TriggerEvent>,
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+    #[arg(long)]
+    daemon: bool,
+    #[arg(long)]
+    label: Option<String>,
+    #[arg(long)]
+    cwd_invocation: bool,
+    #[arg(long, requires = "once")]
+    track: bool,
+    #[arg(skip)]
+    workdir: Option<PathBuf>,
+    #[arg(skip)]
+    task_name: Option<String>,
+    #[arg(skip)]
+    shallow: Vec<PathBuf>,
+    #[arg(skip)]
+    extensions: Vec<String>,
+    #[arg(skip)]
+    watch_new_dirs: bool,
+    #[arg(skip)]
+    extra_args: Vec<String>,
+}
+
+impl WatchOptions {
+    fn prefix_label(&self) -> Option<&str> {
+        self.prefix.then(|| self.label.as_deref().unwrap_or("run"))
+    }
 }
 
 #[derive(Subcommand)]
@@ -72,24 +781,43 @@ enum Commands {
         action: TaskAction,
     },
     Run {
-        name: Option<String>,
+        names: Vec<String>,
         #[arg(short, long, num_args = 1..)]
         watch: Option<Vec<String>>,
-        #[arg(short, long)]
-        run: Option<String>,
+        #[arg(short, long, num_args = 1, action = clap::ArgAction::Append)]
+        run: Option<Vec<String>>,
         #[arg(short, long, num_args = 1..)]
         extensions: Option<Vec<String>>,
-        #[arg(long, short, default_value_t = DEBOUNCE_MS)]
-        debounce: u64,
         #[arg(long, short)]
         global: bool,
-        #[arg(long, short)]
-        quiet: bool,
-        #[arg(long, short)]
-        no_clear: bool,
+        #[command(flatten)]
+        opts: Box<WatchOptions>,
+        #[arg(last = true)]
+        extra_args: Vec<String>,
     },
     Init {
         template: Option<String>,
+        #[arg(long)]
+        list: bool,
+        #[arg(long)]
+        force: bool,
+        #[arg(long)]
+        stdout: bool,
+    },
+    Validate {
+        #[arg(long, short)]
+        global: bool,
+    },
+    Completions {
+        shell: Shell,
+    },
+    Stop {
+        #[arg(long)]
+        pid_file: PathBuf,
+    },
+    Where {
+        #[arg(long, short)]
+        global: bool,
     },
 }
 
@@ -100,487 +828,3577 @@ enum TaskAction {
         name: String,
         #[arg(short, long, num_args = 1.., group = "source")]
         watch: Vec<String>,
-        #[arg(short, long)]
-        run: String,
+        #[arg(short, long, num_args = 1, action = clap::ArgAction::Append, required = true)]
+        run: Vec<String>,
         #[arg(short, long, num_args = 1.., group = "source")]
         extensions: Option<Vec<String>>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        strict: bool,
     },
     Remove {
         name: String,
+        #[arg(short, long)]
+        yes: bool,
+    },
+    List {
+        #[arg(long)]
+        json: bool,
+        #[arg(long, conflicts_with = "json")]
+        names_only: bool,
+    },
+    Show {
+        name: String,
     },
-    List,
     #[command(group = clap::ArgGroup::new("edit_fields").required(true).multiple(true))]
     Edit {
         name: String,
         #[arg(short, long, num_args = 1.., group = "edit_fields")]
         watch: Vec<String>,
-        #[arg(short, long, group = "edit_fields")]
-        run: Option<String>,
+        #[arg(short, long, num_args = 1, action = clap::ArgAction::Append, group = "edit_fields")]
+        run: Option<Vec<String>>,
         #[arg(short, long, num_args = 1.., group = "edit_fields")]
         extensions: Option<Vec<String>>,
+        #[arg(long, group = "edit_fields")]
+        description: Option<String>,
+        #[arg(long)]
+        strict: bool,
     },
     Rename {
         name: String,
         new_name: String,
     },
+    Copy {
+        name: String,
+        new_name: String,
+    },
+    Export {
+        path: Option<PathBuf>,
+    },
+    Import {
+        path: PathBuf,
+        #[arg(long)]
+        overwrite: bool,
+    },
 }
 
+#[derive(Clone)]
 struct ParsedCommand {
     cmd: String,
     args: Vec<String>,
+    shell: bool,
+    env: Vec<(String, String)>,
+    dir: Option<PathBuf>,
 }
 
-fn parse_command(run: &str) -> ParsedCommand {
+fn parse_command(run: &str, shell: bool) -> ParsedCommand {
+    if shell {
+        let (cmd, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        return ParsedCommand {
+            cmd: cmd.to_string(),
+            args: vec![flag.to_string(), run.to_string()],
+            shell: true,
+            env: Vec::new(),
+            dir: None,
+        };
+    }
     let parts = split(run).unwrap_or_else(|e| {
         eprintln!("{} failed to parse command: {}", "Error:".red(), e);
-        process::exit(1);
+        finish(1);
     });
     if parts.is_empty() {
         eprintln!("{} empty command", "Error:".red());
-        process::exit(1);
+        finish(1);
     }
     ParsedCommand {
         cmd: parts[0].clone(),
         args: parts[1..].to_vec(),
+        shell: false,
+        env: Vec::new(),
+        dir: None,
     }
 }
 
-fn load_config(from_global: bool) -> CueConfig {
-    if from_global {
-        confy::load::<CueConfig>("cue", None).unwrap_or_else(|_| {
-            eprintln!("{} failed to read config", "Error:".red());
-            process::exit(1);
-        })
+// `cue run <task> -- <args>` adds to the task's command for that one run;
+// in a sequence, only the last command gets them
+fn append_args(steps: &mut [Step], extra: &[String]) {
+    if extra.is_empty() {
+        return;
+    }
+    let Some(step) = steps.iter_mut().rev().find(|s| s.stage == Stage::Main) else {
+        return;
+    };
+    let command = &mut step.command;
+    if command.shell {
+        if let Some(script) = command.args.last_mut() {
+            script.push(' ');
+            script.push_str(&shell_words::join(extra));
+        }
     } else {
-        let content = fs::read_to_string("cue.toml").unwrap_or_else(|_| {
-            eprintln!("{} failed to read cue.toml", "Error:".red());
-            process::exit(1);
-        });
-        toml::from_str(&content).unwrap_or_else(|e| {
-            eprintln!("{} invalid cue.toml: {}", "Error:".red(), e);
-            process::exit(1);
-        })
+        command.args.extend(extra.iter().cloned());
     }
 }
 
-fn resolve_config(global: bool, quiet: bool) -> CueConfig {
-    if global {
-        log!(quiet, "{} loading global tasks", CUE.green());
-        load_config(true)
-    } else if Path::new("cue.toml").exists() {
-        log!(quiet, "{} loading tasks from 'cue.toml'", CUE.green());
-        load_config(false)
-    } else {
-        log!(quiet, "{} loading global tasks", CUE.green());
-        load_config(true)
-    }
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Before,
+    Main,
+    After,
 }
 
-fn pick_task(config: &CueConfig, name: Option<String>, quiet: bool) -> String {
-    if let Some(n) = name {
-        return n;
-    }
-    if let Some(d) = &config.default {
-        log!(quiet, "{} default task '{}' — running it", CUE.green(), d);
-        return d.clone();
-    }
-    let tasks: Vec<&String> = config.tasks.keys().collect();
-    let choice = Select::new()
-        .with_prompt("which task do you want to run?")
-        .items(&tasks)
-        .interact()
-        .unwrap_or_else(|_| {
-            eprintln!("{} cancelled", "Error:".red());
-            process::exit(1);
-        });
-    tasks[choice].to_string()
+#[derive(Clone)]
+struct Step {
+    stage: Stage,
+    command: ParsedCommand,
 }
 
-fn validate_paths(paths: &[&Path], quiet: bool) {
-    log!(quiet, "{} checking paths...", CUE.green());
-    for path in paths {
-        if path.exists() {
+fn plan_steps(
+    before: &[String],
+    runs: &[String],
+    after: &[String],
+    shell: bool,
+    env: &[(String, String)],
+) -> Vec<Step> {
+    let stage = |stage: Stage, runs: &[String]| {
+        runs.iter()
+            .map(|r| Step {
+                stage,
+                command: ParsedCommand {
+                    env: env.to_vec(),
+                    ..parse_command(r, shell)
+                },
+            })
+            .collect::<Vec<_>>()
+    };
+    [
+        stage(Stage::Before, before),
+        stage(Stage::Main, runs),
+        stage(Stage::After, after),
+    ]
+    .concat()
+}
+
+// index of the step to run after `step` exited with `status`: a failing
+// hook or command skips ahead to the after hooks, which always run
+fn advance(
+    steps: &[Step],
+    step: usize,
+    status: ExitStatus,
+    keep_going: bool,
+    quiet: bool,
+) -> usize {
+    if status.success() {
+        return step + 1;
+    }
+    let first_after = steps
+        .iter()
+        .position(|s| s.stage == Stage::After)
+        .unwrap_or(steps.len());
+    match steps[step].stage {
+        Stage::Before => {
             log!(
                 quiet,
-                "  {} {}",
-                path.display().to_string().cyan(),
-                "exists".green()
+                "{} {}",
+                tag(),
+                "before hook failed — skipping the run".red()
             );
-        } else {
-            eprintln!("{} '{}' doesn't exist", "Error:".red(), path.display());
-            process::exit(1);
+            first_after
+        }
+        Stage::Main if !keep_going => {
+            if step + 1 < first_after {
+                log!(quiet, "{} skipping the remaining commands", tag());
+            }
+            first_after
+        }
+        Stage::Main => step + 1,
+        Stage::After => {
+            log!(quiet, "{} {}", tag(), "after hook failed".red());
+            step + 1
         }
     }
 }
 
-fn validate_command(command: &ParsedCommand, quiet: bool) {
-    log!(quiet, "{} checking command...", CUE.green());
-    if which::which(&command.cmd).is_err() {
-        eprintln!("{} command '{}' not found", "Error:".red(), command.cmd);
-        process::exit(1);
-    }
-    log!(quiet, "  '{}' {}", command.cmd, "found".green());
-}
-
-fn find_by_extensions(extensions: &[String]) -> Vec<PathBuf> {
-    WalkDir::new(".")
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .map(|e| e.path().to_path_buf())
-        .filter(|p| {
-            p.extension()
-                .map(|e| extensions.iter().any(|ext| ext.as_str() == e))
-                .unwrap_or(false)
-        })
-        .collect()
-}
-
-fn resolve_paths(watch: Vec<String>, extensions: Option<Vec<String>>) -> Vec<String> {
-    match extensions {
-        Some(exts) if !exts.is_empty() => {
-            let cache_path = std::env::temp_dir().join("cue_path_cache.json");
-            let dirs_mtime: u64 = watch
-                .iter()
-                .filter_map(|w| fs::metadata(w).ok())
-                .filter_map(|m| m.modified().ok())
-                .filter_map(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                .map(|d| d.as_secs())
-                .sum();
-
-            if let Ok(cached) = fs::read_to_string(&cache_path) {
-                if let Ok((stored_mtime, stored_watch, stored_exts, paths)) =
-                    serde_json::from_str::<(u64, Vec<String>, Vec<String>, Vec<String>)>(&cached)
-                {
-                    if stored_mtime == dirs_mtime && stored_watch == watch && stored_exts == exts {
-                        return paths;
-                    }
+// `changed` holds every file touched in the debounce window, the one that
+// triggered the run first
+fn expand_placeholders(
+    command: &ParsedCommand,
+    changed: &[PathBuf],
+    kind: Option<&str>,
+) -> ParsedCommand {
+    let files: Vec<String> = changed.iter().map(|p| p.display().to_string()).collect();
+    let changed = changed.first();
+    let file = changed.map(|p| p.display().to_string()).unwrap_or_default();
+    let name = changed
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let dir = changed
+        .and_then(|p| p.parent())
+        .map(|d| d.display().to_string())
+        .unwrap_or_default();
+    let ext = changed
+        .and_then(|p| p.extension())
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let joined = files.join(" ");
+    // a shell would otherwise read a file name like `a;rm -rf ~;#.txt` as
+    // commands of its own
+    let quote = |s: &str| {
+        if command.shell {
+            shell_words::quote(s).into_owned()
+        } else {
+            s.to_string()
+        }
+    };
+    let values = [
+        (
+            "{files}",
+            files.iter().map(|f| quote(f)).collect::<Vec<_>>().join(" "),
+        ),
+        ("{file}", quote(&file)),
+        ("{name}", quote(&name)),
+        ("{dir}", quote(&dir)),
+        ("{ext}", quote(&ext)),
+    ];
+    // one pass over the template, so a file name that itself contains
+    // `{dir}` isn't expanded again inside its own quotes
+    let expand = |s: &str| {
+        let mut out = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match values.iter().find(|(key, _)| rest.starts_with(key)) {
+                Some((key, value)) => {
+                    out.push_str(value);
+                    rest = &rest[key.len()..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
                 }
             }
-
-            let paths: Vec<String> = find_by_extensions(&exts)
-                .iter()
-                .map(|p| p.display().to_string())
-                .collect();
-
-            let _ = fs::write(
-                &cache_path,
-                serde_json::to_string(&(dirs_mtime, &watch, &exts, &paths)).unwrap_or_default(),
-            );
-
-            paths
         }
-        _ => watch,
+        out.push_str(rest);
+        out
+    };
+    let mut env = command.env.clone();
+    if !files.is_empty() {
+        env.push(("CUE_CHANGED_FILES".to_string(), joined.clone()));
+        env.push(("CUE_CHANGED_PATH".to_string(), file.clone()));
+        env.push(("CUE_CHANGED_NAME".to_string(), name.clone()));
+        if let Some(kind) = kind {
+            env.push(("CUE_EVENT_KIND".to_string(), kind.to_string()));
+        }
+    }
+    ParsedCommand {
+        cmd: expand(&command.cmd),
+        // a bare {files} argument becomes one argument per file
+        args: command
+            .args
+            .iter()
+            .flat_map(|a| {
+                if a == "{files}" {
+                    files.clone()
+                } else {
+                    vec![expand(a)]
+                }
+            })
+            .collect(),
+        shell: command.shell,
+        env,
+        dir: command.dir.clone(),
     }
 }
 
-fn run_task(
-    config: &CueConfig,
-    name: Option<String>,
-    watch_override: Option<Vec<String>>,
-    run_override: Option<String>,
-    extensions_override: Option<Vec<String>>,
-    debounce: u64,
-    quiet: bool,
-    no_clear: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let name = pick_task(config, name, quiet);
-    let task = config.tasks.get(&name).cloned().unwrap_or_else(|| {
-        eprintln!("{} task '{}' not found", "Error:".red(), name);
-        process::exit(1);
-    });
-
-    let extensions = extensions_override.or(task.extensions);
-    let watch_strs = resolve_paths(watch_override.unwrap_or(task.watch), extensions);
-    let run_str = run_override.or(task.run).unwrap_or_else(|| {
+fn load_env_file(path: Option<&Path>) -> Vec<(String, String)> {
+    let Some(path) = path else {
+        return Vec::new();
+    };
+    let iter = dotenvy::from_path_iter(path).unwrap_or_else(|e| {
         eprintln!(
-            "{} task has no run command — provide one with -r",
-            "Error:".red()
+            "{} failed to read env file '{}': {}",
+            "Error:".red(),
+            path.display(),
+            e
         );
-        process::exit(1);
+        finish(1);
     });
-
-    let paths: Vec<&Path> = watch_strs.iter().map(|s| Path::new(s)).collect();
-    let command = parse_command(&run_str);
-    validate_paths(&paths, quiet);
-    validate_command(&command, quiet);
-    start_watcher(paths, command, &run_str, debounce, quiet, no_clear)
+    iter.collect::<Result<_, _>>().unwrap_or_else(|e| {
+        eprintln!(
+            "{} invalid env file '{}': {}",
+            "Error:".red(),
+            path.display(),
+            e
+        );
+        finish(1);
+    })
+}
+
+fn load_config(path: Option<&Path>) -> CueConfig {
+    let mut config = read_config(path);
+    let source = path
+        .map(Path::to_path_buf)
+        .or_else(|| confy::get_configuration_file_path("cue", None).ok())
+        .map(|path| fs::canonicalize(&path).unwrap_or(path));
+    for task in config.tasks.values_mut() {
+        task.source = source.clone();
+    }
+    config
+}
+
+fn read_config(path: Option<&Path>) -> CueConfig {
+    let Some(path) = path else {
+        return confy::load::<CueConfig>("cue", None).unwrap_or_else(|_| {
+            eprintln!("{} failed to read config", "Error:".red());
+            finish(1);
+        });
+    };
+    let name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    let content = fs::read_to_string(path).unwrap_or_else(|_| {
+        eprintln!("{} failed to read {}", "Error:".red(), name);
+        finish(1);
+    });
+    let parsed = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => serde_norway::from_str(&content).map_err(|e| e.to_string()),
+        Some("json") => serde_json::from_str(&content).map_err(|e| e.to_string()),
+        _ => toml::from_str(&content).map_err(|e| e.to_string()),
+    };
+    parsed.unwrap_or_else(|e| {
+        eprintln!("{} invalid {}: {}", "Error:".red(), name, e);
+        finish(1);
+    })
+}
+
+// task edits go through toml_edit so hand-written comments and layout survive
+fn load_config_document() -> (PathBuf, DocumentMut) {
+    load_config(None);
+    let path = confy::get_configuration_file_path("cue", None).unwrap_or_else(|_| {
+        eprintln!("{} failed to locate config", "Error:".red());
+        finish(1);
+    });
+    let doc = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| content.parse::<DocumentMut>().ok())
+        .unwrap_or_else(|| {
+            eprintln!("{} failed to read config", "Error:".red());
+            finish(1);
+        });
+    (path, doc)
+}
+
+fn store_config_document(path: &Path, doc: &DocumentMut) -> std::io::Result<()> {
+    fs::write(path, doc.to_string())
+}
+
+#[derive(Serialize, Deserialize)]
+struct LastRun {
+    time: String,
+    code: i32,
+}
+
+// --track keeps each task's last --once result in a state file next to the
+// global config, for `task list` to show. results are grouped by the config
+// file the task came from, since a local task can share a global one's name
+fn state_path() -> Option<PathBuf> {
+    confy::get_configuration_file_path("cue", None)
+        .ok()
+        .map(|path| path.with_file_name("state.json"))
+}
+
+type RunState = BTreeMap<PathBuf, BTreeMap<String, LastRun>>;
+
+fn load_state() -> RunState {
+    state_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn record_run(source: &Path, name: &str, code: i32) {
+    update_state(|state| {
+        state.entry(source.to_path_buf()).or_default().insert(
+            name.to_string(),
+            LastRun {
+                time: Utc::now().to_rfc3339(),
+                code,
+            },
+        );
+    });
+}
+
+fn update_state(change: impl FnOnce(&mut RunState)) {
+    // several tasks can finish at once with `cue run a b --once`
+    static STATE: Mutex<()> = Mutex::new(());
+    let _guard = STATE.lock().unwrap();
+    let Some(path) = state_path() else {
+        return;
+    };
+    let mut state = load_state();
+    change(&mut state);
+    let json = serde_json::to_string_pretty(&state).unwrap_or_default();
+    if let Err(e) = fs::write(&path, json) {
+        eprintln!(
+            "{} failed to save the run status to '{}': {}",
+            "Warning:".yellow(),
+            path.display(),
+            e
+        );
+    }
+}
+
+fn format_ago(d: TimeDelta) -> String {
+    match d.num_seconds().max(0) {
+        s if s < 60 => format!("{}s ago", s),
+        s if s < 3600 => format!("{}m ago", s / 60),
+        s if s < 86400 => format!("{}h ago", s / 3600),
+        s => format!("{}d ago", s / 86400),
+    }
+}
+
+fn last_run_summary(last: &LastRun) -> ColoredString {
+    let ago = DateTime::parse_from_rfc3339(&last.time)
+        .map(|t| format!(" {}", format_ago(Utc::now().signed_duration_since(t))))
+        .unwrap_or_default();
+    if last.code == 0 {
+        format!("last: ok{}", ago).green()
+    } else {
+        format!("last: failed{}", ago).red()
+    }
+}
+
+fn tasks_table(doc: &mut DocumentMut) -> &mut Table {
+    doc.entry("tasks")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_mut()
+        .unwrap_or_else(|| {
+            eprintln!("{} invalid config: 'tasks' is not a table", "Error:".red());
+            finish(1);
+        })
+}
+
+fn task_item(task: &Task) -> Item {
+    let doc: DocumentMut = toml::to_string(task)
+        .expect("failed to serialize task")
+        .parse()
+        .expect("failed to serialize task");
+    Item::Table(doc.as_table().clone())
+}
+
+fn strings_item(values: Vec<String>) -> Item {
+    value(values.into_iter().collect::<toml_edit::Array>())
+}
+
+fn run_item(run: Vec<String>) -> Item {
+    match RunCommand::from(run) {
+        RunCommand::Single(command) => value(command),
+        RunCommand::Sequence(commands) => strings_item(commands),
+    }
+}
+
+// keeps the comment trailing the old value, if any
+fn set_field(task: &mut dyn TableLike, key: &str, mut item: Item) {
+    if let (Some(old), Some(new)) = (task.get(key).and_then(Item::as_value), item.as_value_mut()) {
+        *new.decor_mut() = old.decor().clone();
+    }
+    task.insert(key, item);
+}
+
+// commands run from the config's directory, which cue moves into when it
+// loads it, unless --cwd-invocation keeps them where cue was started
+fn invocation_dir(opts: &WatchOptions) -> Option<PathBuf> {
+    if opts.cwd_invocation {
+        std::env::current_dir().ok()
+    } else {
+        None
+    }
+}
+
+fn find_local_config() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .flat_map(|dir| CONFIG_FILES.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+// paths in a cue.toml are relative to the directory it lives in, so cue
+// moves there before resolving anything
+fn load_local_config(path: &Path, quiet: bool) -> CueConfig {
+    let path = path.canonicalize().unwrap_or_else(|_| {
+        eprintln!(
+            "{} config file '{}' doesn't exist",
+            "Error:".red(),
+            path.display()
+        );
+        finish(1);
+    });
+    let dir = path.parent().unwrap_or(Path::new("."));
+    if std::env::current_dir().is_ok_and(|cwd| cwd == dir) {
+        log!(
+            quiet,
+            "{} loading tasks from '{}'",
+            tag(),
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+    } else {
+        log!(quiet, "{} loading tasks from '{}'", tag(), path.display());
+        std::env::set_current_dir(dir).unwrap_or_else(|e| {
+            eprintln!(
+                "{} failed to enter '{}': {}",
+                "Error:".red(),
+                dir.display(),
+                e
+            );
+            finish(1);
+        });
+    }
+    load_config(Some(&path))
+}
+
+// local tasks win on name collisions. only a local default counts: a project
+// without one gets the picker instead of some global task it never mentions.
+// each file's [defaults] only fill in that file's own tasks
+fn merge_configs(global: CueConfig, mut local: CueConfig) -> CueConfig {
+    let default = local.default.take();
+    let mut tasks = global.into_resolved_tasks();
+    tasks.extend(local.into_resolved_tasks());
+    CueConfig {
+        default,
+        defaults: None,
+        tasks,
+    }
+}
+
+fn resolve_config(
+    global: bool,
+    config: Option<&Path>,
+    merge: bool,
+    quiet: bool,
+    verbose: u8,
+) -> CueConfig {
+    let global_path = || {
+        confy::get_configuration_file_path("cue", None)
+            .map(|p| p.display().to_string())
+            .unwrap_or_default()
+    };
+    let local = if let Some(path) = config {
+        debug!(verbose, "config file '{}'", path.display());
+        load_local_config(path, quiet)
+    } else if !global && let Some(path) = find_local_config() {
+        debug!(verbose, "config file '{}'", path.display());
+        load_local_config(&path, quiet)
+    } else {
+        log!(quiet, "{} loading global tasks", tag());
+        debug!(verbose, "global config '{}'", global_path());
+        return load_config(None);
+    };
+    if merge {
+        debug!(verbose, "merging global config '{}'", global_path());
+        merge_configs(load_config(None), local)
+    } else {
+        local
+    }
+}
+
+fn print_task(name: &str, task: &Task, is_default: bool) {
+    println!(
+        "{} {}{}",
+        tag(),
+        name.cyan(),
+        if is_default { " (default)" } else { "" }
+    );
+    if let Some(x) = &task.description {
+        println!("  {}", x);
+    }
+    println!("  watch:");
+    for path in &task.watch {
+        println!("    {}", path);
+    }
+    for path in task.watch_shallow.iter().flatten() {
+        println!("    {} (shallow)", path);
+    }
+    let field = |label: &str, value: String| println!("  {}: {}", label, value);
+    if let Some(x) = &task.extensions {
+        field("extensions", x.join(", "));
+    }
+    let steps = |r: &RunCommand| r.steps().join(" then ");
+    if let Some(x) = &task.deps {
+        field("deps", x.join(", "));
+    }
+    if let Some(x) = &task.before {
+        field("before", steps(x));
+    }
+    match (&task.run, &task.cmd) {
+        (None, Some(cmd)) => {
+            field("cmd", cmd.clone());
+            field(
+                "args",
+                format!("{:?}", task.args.clone().unwrap_or_default()),
+            );
+        }
+        (run, _) => field(
+            "run",
+            run.as_ref()
+                .map(steps)
+                .unwrap_or_else(|| "none".to_string()),
+        ),
+    }
+    if let Some(x) = &task.after {
+        field("after", steps(x));
+    }
+    if let Some(x) = task.shell {
+        field("shell", x.to_string());
+    }
+    if let Some(x) = &task.ignore {
+        field("ignore", x.join(", "));
+    }
+    if let Some(x) = &task.exclude {
+        field("exclude", x.join(", "));
+    }
+    if let Some(x) = task.run_on_start {
+        field("run_on_start", x.to_string());
+    }
+    if let Some(x) = &task.env_file {
+        field("env_file", x.display().to_string());
+    }
+    if let Some(x) = &task.env {
+        let vars: Vec<String> = x.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        field("env", vars.join(", "));
+    }
+    if let Some(x) = task.debounce {
+        field("debounce", format!("{}ms", x));
+    }
+    if let Some(x) = task.quiet {
+        field("quiet", x.to_string());
+    }
+    if let Some(x) = task.no_clear {
+        field("no_clear", x.to_string());
+    }
+    if let Some(x) = task.timeout {
+        field("timeout", format!("{}ms", x));
+    }
+    if let Some(x) = &task.color {
+        field("color", x.clone());
+    }
+    if let Some(x) = task.timestamp {
+        field(
+            "timestamp",
+            match x {
+                Timestamp::Local => "local",
+                Timestamp::Utc => "utc",
+                Timestamp::Off => "off",
+            }
+            .to_string(),
+        );
+    }
+}
+
+fn is_default(config: &CueConfig, name: &str) -> bool {
+    config
+        .default
+        .as_ref()
+        .is_some_and(|d| d.names().iter().any(|n| n == name))
+}
+
+// a list default brings up every task in it at once
+fn several_defaults(config: &CueConfig, quiet: bool) -> Option<Vec<String>> {
+    let Some(DefaultTask::Several(names)) = &config.default else {
+        return None;
+    };
+    if names.len() < 2 {
+        return None;
+    }
+    log!(
+        quiet,
+        "{} default tasks '{}' — running them",
+        tag(),
+        names.join("', '")
+    );
+    Some(names.clone())
+}
+
+fn pick_task(config: &CueConfig, name: Option<String>, quiet: bool) -> String {
+    if let Some(n) = name {
+        return n;
+    }
+    if let Some(d) = config
+        .default
+        .as_ref()
+        .and_then(|d| d.names().into_iter().next())
+    {
+        log!(quiet, "{} default task '{}' — running it", tag(), d);
+        return d;
+    }
+    // with nobody at the keyboard the picker would wait forever
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "{} no default task — name the task to run, e.g. `cue run <task>`",
+            "Error:".red()
+        );
+        finish(1);
+    }
+    let tasks: Vec<&String> = config.tasks.keys().collect();
+    // descriptions are shown and searched too, the choice maps back by index
+    let items: Vec<String> = config
+        .tasks
+        .iter()
+        .map(|(name, task)| match &task.description {
+            Some(description) => format!("{} — {}", name, description),
+            None => name.clone(),
+        })
+        .collect();
+    let choice = FuzzySelect::new()
+        .with_prompt("which task do you want to run?")
+        .items(&items)
+        .interact()
+        .unwrap_or_else(|_| {
+            eprintln!("{} cancelled", "Error:".red());
+            finish(1);
+        });
+    tasks[choice].to_string()
+}
+
+// every missing path is reported before exiting, so typos get fixed in one go
+fn validate_paths(paths: &[&Path], wait: bool, quiet: bool) {
+    log!(quiet, "{} checking paths...", tag());
+    let mut missing = 0;
+    for path in paths {
+        if path.is_symlink()
+            && path.exists()
+            && let Ok(target) = fs::canonicalize(path)
+        {
+            log!(
+                quiet,
+                "  {} {} {}",
+                path.display().to_string().cyan(),
+                "exists — links to".green(),
+                target.display()
+            );
+        } else if path.exists() {
+            log!(
+                quiet,
+                "  {} {}",
+                path.display().to_string().cyan(),
+                "exists".green()
+            );
+        } else if wait {
+            log!(
+                quiet,
+                "  {} {}",
+                path.display().to_string().cyan(),
+                "doesn't exist yet — waiting for it".yellow()
+            );
+        } else {
+            eprintln!("{} '{}' doesn't exist", "Error:".red(), path.display());
+            missing += 1;
+        }
+    }
+    if missing > 0 {
+        finish(1);
+    }
+}
+
+fn validate_commands(steps: &[Step], quiet: bool) {
+    log!(quiet, "{} checking command...", tag());
+    for Step { command, .. } in steps {
+        if command.shell {
+            log!(quiet, "  running through '{}'", command.cmd);
+            continue;
+        }
+        if which::which(&command.cmd).is_err() {
+            eprintln!("{} command '{}' not found", "Error:".red(), command.cmd);
+            finish(1);
+        }
+        log!(quiet, "  '{}' {}", command.cmd, "found".green());
+    }
+}
+
+fn task_problems(task: &Task) -> Vec<String> {
+    let mut problems = Vec::new();
+    for entry in task.watch.iter().chain(task.watch_shallow.iter().flatten()) {
+        let entry = match shellexpand::full(entry) {
+            Ok(expanded) => expanded.into_owned(),
+            Err(e) => {
+                problems.push(format!(
+                    "can't expand '{}': ${} isn't set",
+                    entry, e.var_name
+                ));
+                continue;
+            }
+        };
+        if entry.contains(['*', '?', '[']) {
+            if glob(&entry).map_or(true, |mut g| g.next().is_none()) {
+                problems.push(format!("'{}' doesn't match any files", entry));
+            }
+        } else if !Path::new(&entry).exists() {
+            problems.push(format!("'{}' doesn't exist", entry));
+        }
+    }
+
+    if let Some(color) = &task.color
+        && color.parse::<Color>().is_err()
+    {
+        problems.push(format!("unknown color '{}'", color));
+    }
+
+    let Some(main) = task.runs() else {
+        problems.push("no run command".to_string());
+        return problems;
+    };
+    if task.shell.unwrap_or(false) {
+        return problems;
+    }
+    let steps = |r: Option<&RunCommand>| r.map(RunCommand::steps).unwrap_or_default();
+    let runs = steps(task.before.as_ref())
+        .into_iter()
+        .chain(main)
+        .chain(steps(task.after.as_ref()));
+    for run in runs {
+        match split(&run) {
+            Ok(parts) if parts.is_empty() => problems.push("empty command".to_string()),
+            Ok(parts) => {
+                if which::which(&parts[0]).is_err() {
+                    problems.push(format!("command '{}' not found", parts[0]));
+                }
+            }
+            Err(e) => problems.push(format!("failed to parse command '{}': {}", run, e)),
+        }
+    }
+    problems
+}
+
+// tools may be installed later, so problems only fail the edit with --strict
+fn check_task(name: &str, task: &Task, strict: bool) {
+    let problems = task_problems(task);
+    for problem in &problems {
+        if strict {
+            eprintln!("{} task '{}': {}", "Error:".red(), name, problem);
+        } else {
+            eprintln!("{} task '{}': {}", "Warning:".yellow(), name, problem);
+        }
+    }
+    if strict && !problems.is_empty() {
+        finish(1);
+    }
+}
+
+fn validate_config(config: &CueConfig) -> usize {
+    let mut count = 0;
+    for d in config.default.iter().flat_map(DefaultTask::names) {
+        if !config.tasks.contains_key(&d) {
+            println!("  {} default task '{}' doesn't exist", "✗".red(), d);
+            count += 1;
+        }
+    }
+    for (name, task) in &config.tasks {
+        let problems = task_problems(task);
+        if problems.is_empty() {
+            println!("  {} {}", "✓".green(), name.cyan());
+            continue;
+        }
+        println!("  {} {}", "✗".red(), name.cyan());
+        for problem in &problems {
+            println!("      {}", problem.red());
+        }
+        count += problems.len();
+    }
+    count
+}
+
+// returns the matching files along with every directory the walk went
+// through, whose mtimes tell whether a cached result is still current
+fn find_by_extensions(
+    extensions: &[String],
+    ignore: &[String],
+    gitignore: bool,
+    follow_symlinks: bool,
+    max_depth: Option<u32>,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let ignore = ignore.to_vec();
+    let walker = WalkBuilder::new(".")
+        .standard_filters(gitignore)
+        .hidden(false)
+        .follow_links(follow_symlinks)
+        .max_depth(max_depth.map(|d| d as usize))
+        .filter_entry(move |e| {
+            !(e.file_type().is_some_and(|t| t.is_dir())
+                && ignore.iter().any(|i| e.file_name() == i.as_str()))
+        })
+        .build();
+    let (mut files, mut dirs) = (Vec::new(), Vec::new());
+    for entry in walker.filter_map(|e| e.ok()) {
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            dirs.push(entry.into_path());
+        } else if matches_extensions(entry.path(), extensions) {
+            files.push(entry.into_path());
+        }
+    }
+    (files, dirs)
+}
+
+// `!` entries exclude by file name suffix and always win, so
+// ["rs", "!.generated.rs"] is every .rs file but the generated ones; an
+// entry with a dot in it, like "spec.ts", matches the whole suffix. Suffixes
+// start at a dot, so regenerated.rs isn't a generated one
+fn matches_extensions(path: &Path, extensions: &[String]) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    let (excluded, included): (Vec<&String>, Vec<&String>) =
+        extensions.iter().partition(|e| e.starts_with('!'));
+    // `-e .d.ts` means the same as `-e d.ts`, so one leading dot is dropped
+    let trim = |e: &str| e.strip_prefix('.').unwrap_or(e).to_string();
+    if excluded
+        .iter()
+        .map(|e| trim(&e[1..]))
+        .any(|ext| name == ext.as_str() || name.ends_with(&format!(".{}", ext)))
+    {
+        return false;
+    }
+    included.is_empty()
+        || included.iter().map(|e| trim(e)).any(|ext| {
+            if ext.contains('.') {
+                name.ends_with(&format!(".{}", ext))
+            } else {
+                path.extension().is_some_and(|e| ext.as_str() == e)
+            }
+        })
+}
+
+// the directories under root, minus ignored ones and, with --max-depth, those
+// whose entries lie more than `depth` levels below it, each to be watched
+// shallowly instead of root recursively
+fn dirs_within(
+    root: &Path,
+    depth: Option<u32>,
+    ignore: &[String],
+    gitignore: bool,
+    follow_symlinks: bool,
+) -> Vec<PathBuf> {
+    let ignore = ignore.to_vec();
+    let mut dirs: Vec<PathBuf> = WalkBuilder::new(root)
+        .standard_filters(gitignore)
+        .hidden(false)
+        .follow_links(follow_symlinks)
+        .max_depth(depth.map(|d| d as usize - 1))
+        .filter_entry(move |e| !ignore.iter().any(|i| e.file_name() == i.as_str()))
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_dir()))
+        .map(|e| e.into_path())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+// `-w -` reads one path per line, taken literally, e.g. from `git ls-files`
+fn read_stdin_paths() -> Vec<String> {
+    let mut seen = HashSet::new();
+    let paths: Vec<String> = std::io::stdin()
+        .lock()
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && seen.insert(line.clone()))
+        .collect();
+    if paths.is_empty() {
+        eprintln!("{} no paths to watch on stdin", "Error:".red());
+        finish(1);
+    }
+    paths
+}
+
+fn expand_path(entry: &str) -> String {
+    shellexpand::full(entry)
+        .map(|expanded| expanded.into_owned())
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "{} can't expand '{}': ${} isn't set",
+                "Error:".red(),
+                entry,
+                e.var_name
+            );
+            finish(1);
+        })
+}
+
+fn expand_globs(watch: Vec<String>) -> Vec<String> {
+    let mut paths = Vec::new();
+    for entry in watch {
+        if !entry.contains(['*', '?', '[']) {
+            paths.push(entry);
+            continue;
+        }
+        let matches: Vec<String> = glob(&entry)
+            .unwrap_or_else(|e| {
+                eprintln!("{} invalid pattern '{}': {}", "Error:".red(), entry, e);
+                finish(1);
+            })
+            .filter_map(|p| p.ok())
+            .map(|p| p.display().to_string())
+            .collect();
+        if matches.is_empty() {
+            eprintln!("{} '{}' doesn't match any files", "Error:".red(), entry);
+            finish(1);
+        }
+        paths.extend(matches);
+    }
+    paths
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+struct PathCacheKey {
+    cwd: PathBuf,
+    extensions: Vec<String>,
+    ignore: Vec<String>,
+    gitignore: bool,
+    follow_symlinks: bool,
+    max_depth: Option<u32>,
+}
+
+// a file added or removed anywhere in the walk changes its directory's mtime,
+// so the cached paths hold as long as every walked directory's mtime does
+#[derive(Serialize, Deserialize)]
+struct PathCache {
+    key: PathCacheKey,
+    dirs: Vec<(String, u128)>,
+    paths: Vec<String>,
+}
+
+fn dir_mtime(dir: &str) -> Option<u128> {
+    let modified = fs::metadata(dir).ok()?.modified().ok()?;
+    modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_nanos())
+}
+
+// returns the paths to watch recursively and the ones to watch shallowly
+fn resolve_paths(
+    watch: Vec<String>,
+    extensions: Option<Vec<String>>,
+    opts: &mut WatchOptions,
+) -> (Vec<String>, Vec<String>) {
+    let from_stdin = watch.iter().any(|w| w == "-");
+    let mut watch = expand_globs(
+        watch
+            .iter()
+            .filter(|w| *w != "-")
+            .map(|w| expand_path(w))
+            .collect(),
+    );
+    if from_stdin {
+        watch.extend(read_stdin_paths());
+    }
+    let ignore = opts.ignore.clone();
+    let gitignore = !opts.no_gitignore;
+    match extensions {
+        // explicit watch paths are watched as they are and the extensions
+        // only filter their events
+        Some(exts) if !exts.is_empty() && watch.is_empty() && !opts.enumerate => {
+            // every kept directory gets a watch of its own, so nothing under
+            // target/ or node_modules/ is watched at any depth
+            let ignore =
+                ignore.unwrap_or_else(|| DEFAULT_IGNORE.iter().map(|i| i.to_string()).collect());
+            let dirs = dirs_within(
+                Path::new("."),
+                opts.max_depth,
+                &ignore,
+                gitignore,
+                opts.follow_symlinks,
+            );
+            opts.watch_new_dirs = true;
+            (
+                Vec::new(),
+                dirs.iter().map(|d| d.display().to_string()).collect(),
+            )
+        }
+        Some(exts) if !exts.is_empty() && watch.is_empty() => {
+            let cache_path = std::env::temp_dir().join("cue_path_cache.json");
+            let key = PathCacheKey {
+                cwd: canonical_path(Path::new(".")),
+                extensions: exts,
+                ignore: ignore
+                    .unwrap_or_else(|| DEFAULT_IGNORE.iter().map(|i| i.to_string()).collect()),
+                gitignore,
+                follow_symlinks: opts.follow_symlinks,
+                max_depth: opts.max_depth,
+            };
+
+            if let Ok(cached) = fs::read_to_string(&cache_path)
+                && let Ok(cache) = serde_json::from_str::<PathCache>(&cached)
+                && cache.key == key
+                && cache
+                    .dirs
+                    .iter()
+                    .all(|(dir, mtime)| dir_mtime(dir) == Some(*mtime))
+            {
+                return (cache.paths, Vec::new());
+            }
+
+            let (files, dirs) = find_by_extensions(
+                &key.extensions,
+                &key.ignore,
+                gitignore,
+                opts.follow_symlinks,
+                opts.max_depth,
+            );
+            let paths: Vec<String> = files.iter().map(|p| p.display().to_string()).collect();
+            let dirs = dirs
+                .iter()
+                .map(|d| d.display().to_string())
+                .filter_map(|d| dir_mtime(&d).map(|mtime| (d, mtime)))
+                .collect();
+
+            let cache = PathCache { key, dirs, paths };
+            let _ = fs::write(
+                &cache_path,
+                serde_json::to_string(&cache).unwrap_or_default(),
+            );
+
+            (cache.paths, Vec::new())
+        }
+        _ => (watch, Vec::new()),
+    }
+}
+
+fn run_task(
+    config: &CueConfig,
+    name: Option<String>,
+    watch_override: Option<Vec<String>>,
+    run_override: Option<Vec<String>>,
+    extensions_override: Option<Vec<String>>,
+    opts: &WatchOptions,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let name = pick_task(config, name, opts.quiet);
+    let task = config.task(&name).unwrap_or_else(|| {
+        eprintln!("{} task '{}' not found", "Error:".red(), name);
+        finish(1);
+    });
+
+    if let Some(color) = &task.color {
+        match color.parse::<Color>() {
+            Ok(color) => set_tag_color(color),
+            Err(()) => eprintln!(
+                "{} unknown color '{}' — using green",
+                "Warning:".yellow(),
+                color
+            ),
+        }
+    }
+
+    let extensions = extensions_override.or(task.extensions.clone());
+    let mut opts = opts.clone();
+    opts.extensions = extensions.clone().unwrap_or_default();
+    opts.ignore = opts.ignore.or(task.ignore.clone());
+    // shallow paths are part of the task's own watch list, so -w replaces them too
+    let mut shallow: Vec<String> = match watch_override {
+        Some(_) => Vec::new(),
+        None => task
+            .watch_shallow
+            .iter()
+            .flatten()
+            .map(|w| expand_path(w))
+            .collect(),
+    };
+    let (mut watch_strs, top_level) = resolve_paths(
+        watch_override.unwrap_or(task.watch.clone()),
+        extensions,
+        &mut opts,
+    );
+    shallow.extend(top_level);
+    let exact = run_override.is_none();
+    let runs = run_override
+        .or(task.runs())
+        .filter(|runs| !runs.is_empty())
+        .unwrap_or_else(|| {
+            eprintln!(
+                "{} task has no run command — provide one with -r",
+                "Error:".red()
+            );
+            finish(1);
+        });
+
+    opts.exclude
+        .extend(task.exclude.clone().unwrap_or_default());
+    opts.no_initial |= task.run_on_start == Some(false);
+    opts.timestamp = opts.timestamp.or(task.timestamp);
+    opts.timeout = opts.timeout.or(task.timeout);
+    opts.debounce = opts.debounce.or(task.debounce);
+    opts.quiet |= task.quiet.unwrap_or(false);
+    opts.no_clear |= task.no_clear.unwrap_or(false);
+    opts.task_name = Some(name);
+    opts.shallow = shallow
+        .iter()
+        .map(|p| canonical_path(Path::new(p)))
+        .collect();
+    watch_strs.extend(shallow);
+
+    let paths: Vec<&Path> = watch_strs.iter().map(Path::new).collect();
+    let mut steps = plan_steps(
+        &task.before.as_ref().map(|b| b.steps()).unwrap_or_default(),
+        &runs,
+        &task.after.as_ref().map(|a| a.steps()).unwrap_or_default(),
+        opts.shell || task.shell.unwrap_or(false),
+        &load_env_file(opts.env_file.as_deref().or(task.env_file.as_deref())),
+    );
+    if exact {
+        task.apply_exact_command(&mut steps);
+    }
+    append_args(&mut steps, &opts.extra_args);
+    for step in &mut steps {
+        step.command.dir = opts.workdir.clone();
+        step.command
+            .env
+            .extend(task.env.clone().unwrap_or_default());
+        if let Some(name) = &opts.task_name {
+            step.command
+                .env
+                .push(("CUE_TASK".to_string(), name.clone()));
+        }
+    }
+    if let Some(dir) = opts
+        .workdir
+        .clone()
+        .or_else(|| std::env::current_dir().ok())
+    {
+        debug!(opts.verbose, "running commands in '{}'", dir.display());
+    }
+    validate_paths(&paths, opts.wait, opts.quiet);
+    validate_commands(&steps, opts.quiet);
+    let name = opts.task_name.as_deref().unwrap_or_default();
+    if opts.dry_run {
+        dry_run(&paths, &steps, &dependency_order(config, name));
+        return Ok(0);
+    }
+    write_pid_file(&opts);
+    if opts.once {
+        interrupt_once();
+    }
+    run_deps(config, name, &opts);
+    if opts.once {
+        let code = run_steps(&steps, &opts);
+        if opts.track
+            && !INTERRUPTED.load(Ordering::Relaxed)
+            && let Some(source) = &task.source
+        {
+            record_run(source, name, code);
+        }
+        return Ok(code);
+    }
+    start_watcher(paths, steps, &runs.join("' then '"), &opts).map(|()| 0)
+}
+
+// each task gets its own watcher thread; output is told apart by a
+// per-task prefix and the screen is never cleared under the others
+fn run_tasks(config: &CueConfig, names: Vec<String>, opts: &WatchOptions) -> i32 {
+    thread::scope(|scope| {
+        let handles: Vec<_> = names
+            .into_iter()
+            .map(|name| {
+                let mut opts = opts.clone();
+                opts.prefix = true;
+                opts.no_clear = true;
+                opts.label = Some(match &opts.label {
+                    Some(label) => format!("{}:{}", label, name),
+                    None => name.clone(),
+                });
+                scope.spawn(move || {
+                    set_tag(opts.label.as_deref());
+                    run_task(config, Some(name), None, None, None, &opts).map_err(|e| e.to_string())
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| match handle.join() {
+                Ok(Ok(code)) => code,
+                Ok(Err(e)) => {
+                    eprintln!("{} {}", "Error:".red(), e);
+                    1
+                }
+                Err(_) => 1,
+            })
+            .fold(0, |code, next| if code == 0 { next } else { code })
+    })
+}
+
+fn build_command(command: &ParsedCommand) -> Command {
+    let mut cmd = Command::new(&command.cmd);
+    cmd.args(&command.args).envs(command.env.iter().cloned());
+    if let Some(dir) = &command.dir {
+        cmd.current_dir(dir);
+    }
+    cmd
+}
+
+// with --prefix or --until the child's output is piped through threads that
+// tag each line and look for the pattern
+fn spawn_command(
+    command: &ParsedCommand,
+    prefix: Option<&str>,
+    own_group: bool,
+) -> std::io::Result<(Child, Vec<JoinHandle<()>>)> {
+    let mut cmd = build_command(command);
+    // a watch run leads a process group of its own, so stopping it takes down
+    // whatever it started too; --once runs stay in cue's group, where the
+    // terminal's Ctrl-C reaches them directly. So does everything on a
+    // terminal: a background group would be stopped by SIGTTIN or SIGTTOU the
+    // moment it read from the terminal or changed its settings, so stop_child
+    // tracks down what those runs started instead
+    #[cfg(unix)]
+    if own_group && !std::io::stdin().is_terminal() {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = own_group;
+    if JSON_EVENTS.load(Ordering::Relaxed) {
+        cmd.stdout(std::io::stderr());
+    }
+    if prefix.is_none() && UNTIL.get().is_none() {
+        return Ok((cmd.spawn()?, Vec::new()));
+    }
+    let label = prefix.map(|l| format!("[{}]", l));
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let mut readers = Vec::new();
+    if let Some(out) = child.stdout.take() {
+        readers.push(forward_lines(out, false, label.clone()));
+    }
+    if let Some(err) = child.stderr.take() {
+        readers.push(forward_lines(err, true, label));
+    }
+    Ok((child, readers))
+}
+
+fn forward_lines(
+    pipe: impl Read + Send + 'static,
+    is_stderr: bool,
+    label: Option<String>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut buf = Vec::new();
+        while reader.read_until(b'\n', &mut buf).is_ok_and(|n| n > 0) {
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\n', '\r']);
+            let to_stderr = is_stderr || JSON_EVENTS.load(Ordering::Relaxed);
+            match &label {
+                Some(label) if to_stderr => eprintln!("{} {}", label.red().dimmed(), line),
+                Some(label) => println!("{} {}", label.dimmed(), line),
+                None if to_stderr => eprintln!("{}", line),
+                None => println!("{}", line),
+            }
+            if let Some(label) = &label {
+                write_log(&format!("{} {}", label, line));
+            }
+            if UNTIL.get().is_some_and(|re| re.is_match(line)) {
+                UNTIL_MATCHED.store(true, Ordering::Relaxed);
+            }
+            buf.clear();
+        }
+    })
+}
+
+fn dry_run(paths: &[&Path], steps: &[Step], deps: &[String]) {
+    // several tasks may print their plans at once; holding the lock keeps
+    // each plan in one piece, and --json-events keeps stdout for the events
+    let mut out: Box<dyn Write> = if JSON_EVENTS.load(Ordering::Relaxed) {
+        Box::new(std::io::stderr().lock())
+    } else {
+        Box::new(std::io::stdout().lock())
+    };
+    writeln!(out, "{} dry run — nothing will be executed", tag()).ok();
+    if !deps.is_empty() {
+        writeln!(out, "  deps: {}", deps.join(", ")).ok();
+    }
+    writeln!(out, "  watch:").ok();
+    for path in paths {
+        writeln!(out, "    {}", path.display()).ok();
+    }
+    print_steps(steps, &mut out);
+}
+
+fn print_steps(steps: &[Step], out: &mut dyn Write) {
+    for step in steps {
+        let command = &step.command;
+        let line = shell_words::join(std::iter::once(&command.cmd).chain(&command.args));
+        let stage = match step.stage {
+            Stage::Before => "before",
+            Stage::Main => "run",
+            Stage::After => "after",
+        };
+        writeln!(out, "  {}: {}", stage, line).ok();
+    }
+}
+
+fn run_once(steps: &[Step], opts: &WatchOptions) -> ! {
+    interrupt_once();
+    finish(run_steps(steps, opts));
+}
+
+fn interrupt_once() {
+    static HANDLER: Once = Once::new();
+    HANDLER.call_once(|| {
+        if let Err(e) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::Relaxed)) {
+            eprintln!("{} can't handle Ctrl-C: {}", "Warning:".yellow(), e);
+        }
+    });
+}
+
+fn run_steps(steps: &[Step], opts: &WatchOptions) -> i32 {
+    let started = Instant::now();
+    emit_event(
+        opts,
+        LifecycleEvent::RunStart {
+            task: opts.task_name.as_deref(),
+            files: &[],
+            time: Utc::now().to_rfc3339(),
+        },
+    );
+    let mut code = 0;
+    let mut step = 0;
+    let mut last = None;
+    while step < steps.len() {
+        let command = expand_placeholders(&steps[step].command, &[], None);
+        let status = spawn_command(&command, opts.prefix_label(), false)
+            .and_then(|(mut child, readers)| {
+                let status = wait_with_timeout(&mut child, opts);
+                for reader in readers {
+                    reader.join().ok();
+                }
+                status
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("{} failed to run '{}': {}", "Error:".red(), command.cmd, e);
+                finish(1);
+            });
+        if !status.success() && code == 0 {
+            code = status.code().unwrap_or(1);
+        }
+        last = Some(status);
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            code = 130;
+            break;
+        }
+        if UNTIL_MATCHED.load(Ordering::Relaxed) {
+            log_until(opts.quiet);
+            code = 0;
+            break;
+        }
+        step = advance(steps, step, status, opts.keep_going, opts.quiet);
+    }
+    run_end(opts, last, started.elapsed());
+    code
+}
+
+fn resolve_deps(
+    config: &CueConfig,
+    name: &str,
+    stack: &mut Vec<String>,
+    order: &mut Vec<String>,
+) -> Result<(), String> {
+    if let Some(start) = stack.iter().position(|n| n == name) {
+        let mut cycle = stack[start..].to_vec();
+        cycle.push(name.to_string());
+        return Err(format!("dependency cycle: {}", cycle.join(" -> ")));
+    }
+    if order.iter().any(|n| n == name) {
+        return Ok(());
+    }
+    stack.push(name.to_string());
+    let deps = config.tasks[name].deps.clone().unwrap_or_default();
+    for dep in &deps {
+        if !config.tasks.contains_key(dep) {
+            return Err(format!("task '{}' depends on unknown task '{}'", name, dep));
+        }
+        resolve_deps(config, dep, stack, order)?;
+    }
+    stack.pop();
+    order.push(name.to_string());
+    Ok(())
+}
+
+fn dependency_order(config: &CueConfig, name: &str) -> Vec<String> {
+    let mut order = Vec::new();
+    if let Err(e) = resolve_deps(config, name, &mut Vec::new(), &mut order) {
+        eprintln!("{} {}", "Error:".red(), e);
+        finish(1);
+    }
+    order.pop();
+    order
+}
+
+fn run_deps(config: &CueConfig, name: &str, opts: &WatchOptions) {
+    for dep in dependency_order(config, name) {
+        let Some(task) = config.task(&dep) else {
+            continue;
+        };
+        let Some(runs) = task.runs() else {
+            eprintln!("{} dependency '{}' has no run command", "Error:".red(), dep);
+            finish(1);
+        };
+        let mut steps = plan_steps(
+            &task.before.as_ref().map(|b| b.steps()).unwrap_or_default(),
+            &runs,
+            &task.after.as_ref().map(|a| a.steps()).unwrap_or_default(),
+            opts.shell || task.shell.unwrap_or(false),
+            &load_env_file(opts.env_file.as_deref().or(task.env_file.as_deref())),
+        );
+        task.apply_exact_command(&mut steps);
+        for step in &mut steps {
+            step.command.dir = opts.workdir.clone();
+            step.command
+                .env
+                .extend(task.env.clone().unwrap_or_default());
+        }
+        validate_commands(&steps, opts.quiet);
+        log!(opts.quiet, "{} running dependency '{}'", tag(), dep);
+        let code = run_steps(
+            &steps,
+            &WatchOptions {
+                task_name: Some(dep.clone()),
+                ..opts.clone()
+            },
+        );
+        if code != 0 {
+            eprintln!(
+                "{} dependency '{}' failed with exit code {}",
+                "Error:".red(),
+                dep,
+                code
+            );
+            finish(code);
+        }
+    }
+}
+
+fn log_until(quiet: bool) {
+    if let Some(re) = UNTIL.get() {
+        log!(quiet, "{} output matched '{}' — stopping", tag(), re);
+    }
+}
+
+fn wait_with_timeout(child: &mut Child, opts: &WatchOptions) -> std::io::Result<ExitStatus> {
+    let limit = opts.timeout.map(Duration::from_millis);
+    let grace = Duration::from_millis(opts.kill_timeout);
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        // the command has printed what we were waiting for, or cue was told
+        // to stop
+        if UNTIL_MATCHED.load(Ordering::Relaxed) || INTERRUPTED.load(Ordering::Relaxed) {
+            return stop_child(child, grace).map_or_else(|| child.wait(), Ok);
+        }
+        if let Some(limit) = limit
+            && started.elapsed() >= limit
+        {
+            log!(
+                opts.quiet,
+                "{} {}",
+                tag(),
+                format!("timed out after {}", format_duration(limit)).red()
+            );
+            return stop_child(child, grace).map_or_else(|| child.wait(), Ok);
+        }
+        thread::sleep(Duration::from_millis(CHILD_POLL_MS));
+    }
+}
+
+fn compile_excludes(exclude: &[String]) -> Vec<Pattern> {
+    let cwd = std::env::current_dir()
+        .and_then(fs::canonicalize)
+        .unwrap_or_default();
+    exclude
+        .iter()
+        .map(|x| {
+            let pattern = if Path::new(x).is_absolute() {
+                x.clone()
+            } else {
+                format!("{}/{}", Pattern::escape(&cwd.to_string_lossy()), x)
+            };
+            Pattern::new(&pattern).unwrap_or_else(|e| {
+                eprintln!("{} invalid exclude '{}': {}", "Error:".red(), x, e);
+                finish(1);
+            })
+        })
+        .collect()
+}
+
+fn is_editor_temp(path: &Path) -> bool {
+    // checked for every path of every event, so compiled only once
+    static PATTERNS: OnceLock<Vec<Pattern>> = OnceLock::new();
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    PATTERNS
+        .get_or_init(|| {
+            EDITOR_TEMP_FILES
+                .iter()
+                .filter_map(|p| Pattern::new(p).ok())
+                .collect()
+        })
+        .iter()
+        .any(|p| p.matches(name))
+}
+
+// with extensions set, a watched directory only triggers on files that have
+// one of them, outside the ignored directories and the project's .gitignore
+struct ExtensionFilter {
+    extensions: Vec<String>,
+    ignore: Vec<String>,
+    gitignore: Option<Gitignore>,
+}
+
+impl ExtensionFilter {
+    fn new(opts: &WatchOptions) -> Option<Self> {
+        if opts.extensions.is_empty() {
+            return None;
+        }
+        let gitignore = (!opts.no_gitignore)
+            .then(std::env::current_dir)
+            .and_then(Result::ok)
+            .and_then(|cwd| {
+                let mut builder = GitignoreBuilder::new(&cwd);
+                builder.add(cwd.join(".gitignore"));
+                builder.add(cwd.join(".ignore"));
+                builder.build().ok()
+            });
+        Some(ExtensionFilter {
+            extensions: opts.extensions.clone(),
+            ignore: opts
+                .ignore
+                .clone()
+                .unwrap_or_else(|| DEFAULT_IGNORE.iter().map(|i| i.to_string()).collect()),
+            gitignore,
+        })
+    }
+
+    fn allows(&self, path: &Path, roots: &[PathBuf]) -> bool {
+        if !matches_extensions(path, &self.extensions) {
+            return false;
+        }
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        // only directories below the watched root count, so watching an
+        // ignored directory by name still works
+        let inside = roots
+            .iter()
+            .find_map(|r| path.strip_prefix(r).ok())
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        if inside
+            .components()
+            .any(|c| self.ignore.iter().any(|i| c.as_os_str() == i.as_str()))
+        {
+            return false;
+        }
+        self.gitignore.as_ref().is_none_or(|g| {
+            !path.starts_with(g.path()) || !g.matched_path_or_any_parents(&path, false).is_ignore()
+        })
+    }
+}
+
+fn is_excluded(path: &Path, excludes: &[Pattern]) -> bool {
+    if excludes.is_empty() {
+        return false;
+    }
+    let path = fs::canonicalize(path).unwrap_or_else(|_| {
+        std::env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    });
+    path.ancestors()
+        .any(|a| excludes.iter().any(|x| x.matches_path(a)))
+}
+
+struct Run {
+    child: Child,
+    started: Instant,
+    step: usize,
+    changed: Vec<PathBuf>,
+    kind: Option<&'static str>,
+    ok: bool,
+}
+
+struct Session {
+    passed: u32,
+    failed: u32,
+    killed: u32,
+    busy: Duration,
+    quiet: bool,
+}
+
+struct Pending {
+    trigger: PathBuf,
+    kind: &'static str,
+    first: Instant,
+    last: Instant,
+    touched: HashSet<PathBuf>,
+    // the batch started while the command was running or had only just
+    // finished, so the command may have made these changes itself
+    during_run: bool,
+}
+
+struct Retry {
+    at: Instant,
+    step: usize,
+    changed: Vec<PathBuf>,
+    kind: Option<&'static str>,
+    ok: bool,
+}
+
+// the event kind reported to the command, named like the --events values
+fn event_kind(kind: &EventKind) -> &'static str {
+    match kind {
+        EventKind::Modify(ModifyKind::Name(_)) => "rename",
+        EventKind::Modify(_) => "modify",
+        EventKind::Create(_) => "create",
+        EventKind::Remove(_) => "remove",
+        EventKind::Access(_) => "access",
+        EventKind::Any | EventKind::Other => "other",
+    }
+}
+
+fn spawn_run(
+    steps: &[Step],
+    step: usize,
+    changed: Vec<PathBuf>,
+    kind: Option<&'static str>,
+    prefix: Option<&str>,
+) -> Option<Run> {
+    let command = expand_placeholders(&steps[step].command, &changed, kind);
+    match spawn_command(&command, prefix, true) {
+        Ok((child, _)) => Some(Run {
+            child,
+            started: Instant::now(),
+            step,
+            changed,
+            kind,
+            ok: true,
+        }),
+        Err(e) => {
+            eprintln!("{} failed to run '{}': {}", tag().red(), command.cmd, e);
+            write_log(&format!("{} failed to run '{}': {}", tag(), command.cmd, e));
+            None
+        }
+    }
+}
+
+fn next_step(
+    steps: &[Step],
+    prev: Run,
+    status: ExitStatus,
+    keep_going: bool,
+    prefix: Option<&str>,
+    quiet: bool,
+) -> Option<Run> {
+    let step = advance(steps, prev.step, status, keep_going, quiet);
+    let ok = prev.ok && status.success();
+    if step < steps.len() {
+        spawn_run(steps, step, prev.changed, prev.kind, prefix).map(|r| Run { ok, ..r })
+    } else {
+        None
+    }
+}
+
+fn stop_child(child: &mut Child, grace: Duration) -> Option<ExitStatus> {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{Signal, kill, killpg};
+        use nix::unistd::Pid;
+
+        // signal the child's whole group when it leads one, and finish off
+        // anything in it that outlived the child; a child in cue's own group
+        // gets the same for its descendants, listed before any of them exits
+        // and leaves its own children to be reparented
+        let pid = Pid::from_raw(child.id() as i32);
+        let grouped = killpg(pid, Signal::SIGTERM).is_ok();
+        let family = if grouped {
+            Vec::new()
+        } else {
+            descendants(child.id())
+        };
+        let signal_family = |signal| {
+            for p in &family {
+                kill(Pid::from_raw(*p as i32), signal).ok();
+            }
+        };
+        let finish_off = || {
+            if grouped {
+                killpg(pid, Signal::SIGKILL).ok();
+            }
+            signal_family(Signal::SIGKILL);
+        };
+        if grouped || kill(pid, Signal::SIGTERM).is_ok() {
+            signal_family(Signal::SIGTERM);
+            let deadline = Instant::now() + grace;
+            while Instant::now() < deadline {
+                if let Ok(Some(status)) = child.try_wait() {
+                    finish_off();
+                    return Some(status);
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        finish_off();
+    }
+    #[cfg(not(unix))]
+    let _ = grace;
+
+    child.kill().ok();
+    child.wait().ok()
+}
+
+// every process below root, from /proc where there is one and from ps
+// elsewhere
+#[cfg(unix)]
+fn descendants(root: u32) -> Vec<u32> {
+    let mut parents: Vec<(u32, u32)> = match fs::read_dir("/proc") {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str()?.parse::<u32>().ok())
+            .filter_map(|pid| {
+                // the command name in parentheses may hold spaces, so the
+                // fields are counted from its closing one
+                let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+                let ppid = stat.rsplit_once(')')?.1.split_whitespace().nth(1)?;
+                Some((pid, ppid.parse().ok()?))
+            })
+            .collect(),
+        Err(_) => Command::new("ps")
+            .args(["-A", "-o", "pid=", "-o", "ppid="])
+            .output()
+            .map(|out| {
+                String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .filter_map(|line| {
+                        let mut fields = line.split_whitespace().map(|f| f.parse().ok());
+                        Some((fields.next()??, fields.next()??))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    let mut found = vec![root];
+    let mut i = 0;
+    while i < found.len() {
+        let parent = found[i];
+        parents.retain(|&(pid, ppid)| {
+            if ppid == parent {
+                found.push(pid);
+            }
+            ppid != parent
+        });
+        i += 1;
+    }
+    found.split_off(1)
+}
+
+fn format_duration(d: Duration) -> String {
+    if d < Duration::from_secs(1) {
+        format!("{}ms", d.as_millis())
+    } else {
+        format!("{:.1}s", d.as_secs_f64())
+    }
+}
+
+fn exit_summary(status: ExitStatus, elapsed: Duration) -> String {
+    let code = status
+        .code()
+        .map(|c| c.to_string())
+        .unwrap_or_else(|| "by signal".to_string());
+    format!("exited {} in {}", code, format_duration(elapsed))
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum LifecycleEvent<'a> {
+    RunStart {
+        task: Option<&'a str>,
+        files: &'a [PathBuf],
+        time: String,
+    },
+    RunEnd {
+        task: Option<&'a str>,
+        code: Option<i32>,
+        duration_ms: u128,
+        time: String,
+    },
+    WatchError {
+        message: String,
+        time: String,
+    },
+}
+
+fn emit_event(opts: &WatchOptions, event: LifecycleEvent) {
+    if opts.json_events
+        && let Ok(line) = serde_json::to_string(&event)
+    {
+        println!("{}", line);
+    }
+}
+
+fn run_end(opts: &WatchOptions, status: Option<ExitStatus>, elapsed: Duration) {
+    emit_event(
+        opts,
+        LifecycleEvent::RunEnd {
+            task: opts.task_name.as_deref(),
+            code: status.and_then(|s| s.code()),
+            duration_ms: elapsed.as_millis(),
+            time: Utc::now().to_rfc3339(),
+        },
+    );
+}
+
+fn report_exit(status: ExitStatus, elapsed: Duration, quiet: bool) {
+    let summary = exit_summary(status, elapsed);
+    log!(
+        quiet,
+        "{} {}",
+        tag(),
+        if status.success() {
+            summary.green()
+        } else {
+            summary.red()
+        }
+    );
+}
+
+// FNV-1a, plenty for telling whether a file's bytes changed between saves
+fn hash_contents(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// backends report paths the way they were watched, so hashes are kept under
+// the absolute path
+fn hash_key(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn contents_changed(path: &Path, hashes: &mut HashMap<PathBuf, u64>) -> bool {
+    let Ok(bytes) = fs::read(path) else {
+        hashes.remove(&hash_key(path));
+        return true;
+    };
+    let hash = hash_contents(&bytes);
+    hashes.insert(hash_key(path), hash) != Some(hash)
+}
+
+// --skip-unchanged compares against what each file held when cue started, so
+// touching a file nobody has edited yet doesn't count as a change; the walk
+// runs in the background to keep it off the startup path
+fn seed_hashes(paths: &[PathBuf], opts: &WatchOptions) -> JoinHandle<HashMap<PathBuf, u64>> {
+    let roots: Vec<(PathBuf, Option<usize>)> = paths
+        .iter()
+        .map(|p| {
+            let depth = if opts.shallow.contains(p) {
+                Some(1)
+            } else {
+                opts.max_depth.map(|d| d as usize)
+            };
+            (p.clone(), depth)
+        })
+        .collect();
+    let excludes = compile_excludes(&opts.exclude);
+    let ignore = opts
+        .ignore
+        .clone()
+        .unwrap_or_else(|| DEFAULT_IGNORE.iter().map(|i| i.to_string()).collect());
+    let follow_symlinks = opts.follow_symlinks;
+    thread::spawn(move || {
+        let mut hashes = HashMap::new();
+        for (root, depth) in roots {
+            let ignore = ignore.clone();
+            let walker = WalkBuilder::new(&root)
+                .standard_filters(false)
+                .hidden(false)
+                .follow_links(follow_symlinks)
+                .max_depth(depth)
+                .filter_entry(move |e| {
+                    e.depth() == 0 || !ignore.iter().any(|i| e.file_name() == i.as_str())
+                })
+                .build();
+            for entry in walker.filter_map(|e| e.ok()) {
+                if entry.file_type().is_some_and(|t| t.is_file())
+                    && !is_excluded(entry.path(), &excludes)
+                    && let Ok(bytes) = fs::read(entry.path())
+                {
+                    hashes.insert(hash_key(entry.path()), hash_contents(&bytes));
+                }
+            }
+        }
+        hashes
+    })
+}
+
+// what wakes a watcher loop: the backend's events, and lines typed on stdin
+enum Message {
+    Event(notify::Result<Event>),
+    Rerun,
+    Enter,
+    Stopped,
+}
+
+type EventReceiver = mpsc::Receiver<Message>;
+
+// the stdin listener holds a sender too, so the channel never disconnects;
+// the backend dropping its handler is reported as a message instead
+struct EventForwarder(mpsc::Sender<Message>);
+
+impl notify::EventHandler for EventForwarder {
+    fn handle_event(&mut self, event: notify::Result<Event>) {
+        self.0.send(Message::Event(event)).ok();
+    }
+}
+
+impl Drop for EventForwarder {
+    fn drop(&mut self) {
+        self.0.send(Message::Stopped).ok();
+    }
+}
+
+fn create_watcher(
+    paths: &[PathBuf],
+    opts: &WatchOptions,
+) -> notify::Result<(Box<dyn Watcher>, EventReceiver)> {
+    let (tx, rx) = mpsc::channel::<Message>();
+    STDIN_LISTENERS.lock().unwrap().push(tx.clone());
+    let tx = EventForwarder(tx);
+    let mut watcher: Box<dyn Watcher> = if opts.poll {
+        let config =
+            notify::Config::default().with_poll_interval(Duration::from_millis(opts.poll_interval));
+        Box::new(PollWatcher::new(tx, config)?)
+    } else {
+        Box::new(recommended_watcher(tx)?)
+    };
+    let mut count = 0;
+    for path in paths {
+        if !path.exists() {
+            watcher.watch(&nearest_existing(path), RecursiveMode::NonRecursive)?;
+            count += 1;
+        } else if opts.shallow.contains(path) {
+            watch_root(watcher.as_mut(), path, RecursiveMode::NonRecursive)?;
+            count += 1;
+        } else if let Some(depth) = opts.max_depth
+            && path.is_dir()
+        {
+            for dir in dirs_within(path, Some(depth), &[], false, opts.follow_symlinks) {
+                watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+                count += 1;
+            }
+        } else {
+            watch_root(watcher.as_mut(), path, RecursiveMode::Recursive)?;
+            count += 1;
+        }
+    }
+    debug!(
+        opts.verbose,
+        "registered {} watch{}",
+        count,
+        if count == 1 { "" } else { "es" }
+    );
+    Ok((watcher, rx))
+}
+
+// a watch on a file follows its inode, which an atomic save replaces, so
+// files are watched through their directory instead
+fn watch_root(watcher: &mut dyn Watcher, path: &Path, mode: RecursiveMode) -> notify::Result<()> {
+    match path.parent() {
+        Some(parent) if path.is_file() => watcher.watch(parent, RecursiveMode::NonRecursive),
+        _ => watcher.watch(path, mode),
+    }
+}
+
+// editors save by writing a temp file and renaming it over the real one, so
+// a rename within a watched directory, or onto a watched file, counts as
+// modifying the destination; returns the source path the rename dropped,
+// which no longer exists
+fn normalize_rename(e: &mut Event, targets: &[PathBuf]) -> Option<PathBuf> {
+    match e.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if e.paths.len() == 2 => {
+            let to = e.paths.pop()?;
+            let from = e.paths.pop();
+            e.paths = vec![to];
+            e.kind = EventKind::Modify(ModifyKind::Data(DataChange::Any));
+            from
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To))
+            if e.paths.first().is_some_and(|to| targets.contains(to)) =>
+        {
+            e.paths.truncate(1);
+            e.kind = EventKind::Modify(ModifyKind::Data(DataChange::Any));
+            None
+        }
+        _ => None,
+    }
+}
+
+// when directories are watched one by one, a directory created after startup
+// needs its own watch (and so do the ones inside it), as long as it isn't
+// ignored and, with --max-depth, its entries still fall within the depth
+// below the outermost root
+fn watch_new_dir(watcher: &mut dyn Watcher, dir: &Path, targets: &[PathBuf], opts: &WatchOptions) {
+    let Some(below) = targets
+        .iter()
+        .filter_map(|t| dir.strip_prefix(t).ok())
+        .map(|rel| rel.components().count())
+        .max()
+    else {
+        return;
+    };
+    if opts.max_depth.is_some_and(|d| below >= d as usize) || !dir.is_dir() {
+        return;
+    }
+    let (ignore, gitignore) = if opts.watch_new_dirs {
+        let ignore = opts
+            .ignore
+            .clone()
+            .unwrap_or_else(|| DEFAULT_IGNORE.iter().map(|i| i.to_string()).collect());
+        (ignore, !opts.no_gitignore)
+    } else {
+        (Vec::new(), false)
+    };
+    if dir
+        .file_name()
+        .is_some_and(|n| ignore.iter().any(|i| n == i.as_str()))
+    {
+        return;
+    }
+    let depth = opts.max_depth.map(|d| d - below as u32);
+    for d in dirs_within(dir, depth, &ignore, gitignore, opts.follow_symlinks) {
+        watcher.watch(&d, RecursiveMode::NonRecursive).ok();
+    }
+}
+
+// inotify's per-user watch limit is low on many distros, and every
+// directory in a recursive watch takes one
+fn watch_limit_hint() {
+    let current = fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+        .map(|limit| format!(" (currently {})", limit.trim()))
+        .unwrap_or_default();
+    eprintln!("  on Linux, raise fs.inotify.max_user_watches{}:", current);
+    eprintln!("    sudo sysctl fs.inotify.max_user_watches=524288");
+    eprintln!(
+        "  and add 'fs.inotify.max_user_watches=524288' to /etc/sysctl.conf to keep it after a reboot"
+    );
+    eprintln!("  or watch less with --max-depth or --ignore, or use --poll instead");
+}
+
+fn nearest_existing(path: &Path) -> PathBuf {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .ancestors()
+        .find(|a| a.exists())
+        .unwrap_or(Path::new("/"))
+        .to_path_buf()
+}
+
+// a missing path is watched through its nearest existing ancestor until it
+// appears, then the watch moves onto the path itself
+struct Waiting {
+    target: PathBuf,
+    anchor: PathBuf,
+}
+
+fn waiting_paths(paths: &[PathBuf]) -> Vec<Waiting> {
+    paths
+        .iter()
+        .filter(|p| !p.exists())
+        .map(|p| Waiting {
+            target: std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf()),
+            anchor: nearest_existing(p),
+        })
+        .collect()
+}
+
+fn promote_waiting(waiting: &mut Vec<Waiting>, watcher: &mut dyn Watcher, quiet: bool) {
+    waiting.retain_mut(|w| {
+        if w.target.exists() {
+            if watch_root(watcher, &w.target, RecursiveMode::Recursive).is_ok() {
+                log!(
+                    quiet,
+                    "{} '{}' appeared — watching it",
+                    tag(),
+                    w.target.display()
+                );
+                return false;
+            }
+            return true;
+        }
+        let anchor = nearest_existing(&w.target);
+        if anchor != w.anchor && watcher.watch(&anchor, RecursiveMode::NonRecursive).is_ok() {
+            w.anchor = anchor;
+        }
+        true
+    });
+}
+
+// nested recursive watches report every change twice, so keep only the
+// outermost of overlapping paths
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+// a shallow path only covers itself, so paths below it are kept
+fn collapse_paths(paths: &[&Path], shallow: &[PathBuf], quiet: bool) -> Vec<PathBuf> {
+    let mut resolved: Vec<(PathBuf, &Path)> =
+        paths.iter().map(|p| (canonical_path(p), *p)).collect();
+    resolved.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut kept: Vec<(PathBuf, &Path)> = Vec::new();
+    for (canonical, original) in resolved {
+        match kept
+            .iter()
+            .find(|(k, _)| *k == canonical || (canonical.starts_with(k) && !shallow.contains(k)))
+        {
+            Some((k, by)) if *k == canonical => log!(
+                quiet,
+                "{} '{}' is the same as '{}' — watching it once",
+                tag(),
+                original.display(),
+                by.display()
+            ),
+            Some((_, by)) => log!(
+                quiet,
+                "{} '{}' is inside '{}' — already watched",
+                tag(),
+                original.display(),
+                by.display()
+            ),
+            None => kept.push((canonical, original)),
+        }
+    }
+    kept.into_iter().map(|(canonical, _)| canonical).collect()
+}
+
+fn notify_finished(name: &str, ok: bool, summary: &str) -> Result<(), notify_rust::error::Error> {
+    notify_rust::Notification::new()
+        .summary(&format!(
+            "cue: {} {}",
+            name,
+            if ok { "succeeded" } else { "failed" }
+        ))
+        .body(summary)
+        .show()
+        .map(|_| ())
 }
 
 fn start_watcher(
     paths: Vec<&Path>,
-    command: ParsedCommand,
+    steps: Vec<Step>,
     run_str: &str,
-    debounce: u64,
-    quiet: bool,
-    no_clear: bool,
+    opts: &WatchOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let width = terminal_size()
-        .map(|(Width(w), _)| w as usize)
-        .unwrap_or(80)
-        / 2;
-    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
-    let mut watcher = recommended_watcher(tx)?;
-
+    let quiet = opts.quiet;
+    if StrftimeItems::new(&opts.time_format).parse().is_err() {
+        eprintln!(
+            "{} invalid time format '{}'",
+            "Error:".red(),
+            opts.time_format
+        );
+        finish(1);
+    }
+    let separator = separator_line(opts);
     log!(
         quiet,
         "{} watching — will run '{}' on changes",
-        CUE.green(),
+        tag(),
         run_str
     );
 
+    let paths = collapse_paths(&paths, &opts.shallow, quiet);
     for path in &paths {
-        watcher.watch(path, RecursiveMode::Recursive)?;
+        debug!(opts.verbose, "watching '{}'", path.display());
+    }
+    let (mut watcher, mut rx) = match create_watcher(&paths, opts) {
+        Err(e) if matches!(e.kind, notify::ErrorKind::MaxFilesWatch) => {
+            eprintln!(
+                "{} too many directories to watch — the OS file watch limit was reached",
+                "Error:".red()
+            );
+            watch_limit_hint();
+            finish(1);
+        }
+        result => result?,
+    };
+    let mut seeding = opts.skip_unchanged.then(|| seed_hashes(&paths, opts));
+    let mut restarted = false;
+    let mut waiting = waiting_paths(&paths);
+    let targets: Vec<PathBuf> = paths
+        .iter()
+        .map(|p| std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf()))
+        .collect();
+
+    print_separator(&separator, quiet);
+
+    let kill_timeout = Duration::from_millis(opts.kill_timeout);
+    // shared with the Ctrl-C handler so the running command doesn't outlive cue
+    let current: Arc<Mutex<Option<Run>>> = Arc::new(Mutex::new(None));
+    RUNNING.lock().unwrap().push(Arc::clone(&current));
+    static HANDLER: Once = Once::new();
+    let mut installed = Ok(());
+    HANDLER.call_once(|| {
+        // the tag is per thread, so the handler's thread takes this one's
+        let tag = tag();
+        installed = ctrlc::set_handler(move || {
+            print_session(&tag);
+            stop_all(kill_timeout);
+            finish(130);
+        });
+    });
+    installed?;
+    SESSION.lock().unwrap().quiet &= quiet;
+    // r + Enter reruns and q + Enter quits, but only when someone is typing
+    let hotkeys = std::io::stdin().is_terminal();
+    if hotkeys || opts.fail_limit.is_some() {
+        listen_stdin(hotkeys, kill_timeout);
+    }
+
+    if opts.watch_only {
+        // shown even with --quiet, as the sign that the watcher is live
+        log!(
+            opts.json_events,
+            "{} ready — watching {} path{}, the command runs on the first change:",
+            tag(),
+            paths.len(),
+            if paths.len() == 1 { "" } else { "s" }
+        );
+        if !opts.json_events {
+            print_steps(&steps, &mut std::io::stdout());
+        }
+    } else if opts.no_initial {
+        log!(quiet, "{} waiting for changes", tag());
+    } else {
+        emit_event(
+            opts,
+            LifecycleEvent::RunStart {
+                task: opts.task_name.as_deref(),
+                files: &[],
+                time: Utc::now().to_rfc3339(),
+            },
+        );
+        let first = spawn_run(&steps, 0, Vec::new(), None, opts.prefix_label());
+        if first.is_none() {
+            finish(1);
+        }
+        *current.lock().unwrap() = first;
     }
 
-    log!(quiet, "{}", "_".repeat(width));
+    let excludes = compile_excludes(&opts.exclude);
+    let extension_filter = ExtensionFilter::new(opts);
+    let mut hashes: HashMap<PathBuf, u64> = HashMap::new();
+    let debounce = Duration::from_millis(opts.debounce.unwrap_or(DEBOUNCE_MS));
+    let max_wait = opts.max_wait.map(Duration::from_millis);
+    // a batch runs once changes stop for the debounce window, or once it's
+    // been pending for --max-wait even if they never stop
+    let settle_left = |p: &Pending| {
+        let quiet_left = debounce.saturating_sub(p.last.elapsed());
+        max_wait.map_or(quiet_left, |m| {
+            quiet_left.min(m.saturating_sub(p.first.elapsed()))
+        })
+    };
+    let child_poll = Duration::from_millis(CHILD_POLL_MS);
+    let timeout = opts.timeout.map(Duration::from_millis);
+    // changes still inside their debounce window, keyed by watched root
+    // with --debounce-per-path and all under one key otherwise
+    let mut pending: BTreeMap<usize, Pending> = BTreeMap::new();
+    let root_of = |p: &Path| {
+        let p = std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+        opts.debounce_per_path
+            .then(|| targets.iter().position(|t| p.starts_with(t)))
+            .flatten()
+            .unwrap_or(0)
+    };
+    let mut can_clear = true;
+    let mut notify = opts.notify;
+    let mut retry: Option<Retry> = None;
+    let mut queued: Option<Vec<PathBuf>> = None;
+    let mut attempts = 0;
+    let min_interval = Duration::from_millis(opts.min_interval.unwrap_or(0));
+    let mut last_finished: Option<Instant> = None;
+    let throttle_left = |last: Option<Instant>| {
+        last.map_or(Duration::ZERO, |f| min_interval.saturating_sub(f.elapsed()))
+    };
+    let mut starts: VecDeque<Instant> = VecDeque::new();
+    let mut storm_warned = false;
+    let mut limit_warned = false;
+    let mut self_triggered = 0;
+    let mut failures = 0;
+    let mut paused = false;
+    // what triggered the latest batch, kept for a queued or throttled run
+    let mut kind: Option<&'static str> = None;
+    loop {
+        // wake up to finish the debounce window, to notice the command
+        // exiting, to retry it and to replay a throttled change, otherwise
+        // block until the next event
+        let mut wait = pending.values().map(&settle_left).min();
+        // --until lines are read on other threads, so keep checking for a match
+        if UNTIL.get().is_some() || current.lock().unwrap().is_some() {
+            wait = Some(wait.map_or(child_poll, |w| w.min(child_poll)));
+        }
+        if let Some(r) = &retry {
+            let left = r.at.saturating_duration_since(Instant::now());
+            wait = Some(wait.map_or(left, |w| w.min(left)));
+        } else if queued.is_some() && current.lock().unwrap().is_none() {
+            let left = throttle_left(last_finished);
+            wait = Some(wait.map_or(left, |w| w.min(left)));
+        }
+        let received = match wait {
+            Some(w) => rx.recv_timeout(w),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        // Enter resumes a paused watcher, and r does too before rerunning
+        let (forced, entered) = match &received {
+            Ok(Message::Rerun) => (true, true),
+            Ok(Message::Enter) => (false, true),
+            _ => (false, false),
+        };
+        let received = match received {
+            Ok(Message::Event(event)) => Ok(event),
+            Ok(Message::Stopped) => Err(RecvTimeoutError::Disconnected),
+            Ok(Message::Rerun | Message::Enter) => Err(RecvTimeoutError::Timeout),
+            Err(e) => Err(e),
+        };
+        if UNTIL_MATCHED.load(Ordering::Relaxed) {
+            log_until(quiet);
+            stop_all(kill_timeout);
+            finish(0);
+        }
+        let mut run = current.lock().unwrap();
+
+        let mut finished = run.as_mut().and_then(|r| r.child.try_wait().ok().flatten());
+        if finished.is_none()
+            && let Some(limit) = timeout
+            && let Some(r) = run.as_mut()
+            && r.started.elapsed() >= limit
+        {
+            log!(
+                quiet,
+                "{} {}",
+                tag(),
+                format!("timed out after {}", format_duration(limit)).red()
+            );
+            finished = stop_child(&mut r.child, kill_timeout);
+        }
+        if let Some(status) = finished
+            && let Some(r) = run.take()
+        {
+            last_finished = Some(Instant::now());
+            let elapsed = r.started.elapsed();
+            SESSION.lock().unwrap().busy += elapsed;
+            let ok = r.ok && status.success();
+            let summary = exit_summary(status, r.started.elapsed());
+            report_exit(status, r.started.elapsed(), quiet);
+            if !status.success() && attempts < opts.retry {
+                let delay = Duration::from_millis(RETRY_BASE_MS << attempts.min(16));
+                attempts += 1;
+                log!(
+                    quiet,
+                    "{} retrying in {} ({}/{})",
+                    tag(),
+                    format_duration(delay),
+                    attempts,
+                    opts.retry
+                );
+                retry = Some(Retry {
+                    at: Instant::now() + delay,
+                    step: r.step,
+                    changed: r.changed,
+                    kind: r.kind,
+                    ok: r.ok,
+                });
+            } else {
+                *run = next_step(
+                    &steps,
+                    r,
+                    status,
+                    opts.keep_going,
+                    opts.prefix_label(),
+                    quiet,
+                );
+                if run.is_none() {
+                    // once per run, on stderr so --json-events stays parseable
+                    if opts.bell && !ok {
+                        eprint!("\x07");
+                    }
+                    run_end(opts, Some(status), elapsed);
+                    let mut session = SESSION.lock().unwrap();
+                    if ok {
+                        session.passed += 1;
+                    } else {
+                        session.failed += 1;
+                    }
+                    drop(session);
+                    if ok {
+                        failures = 0;
+                    } else {
+                        failures += 1;
+                        if failures > 1 {
+                            log!(
+                                quiet,
+                                "{} {}",
+                                tag(),
+                                format!("failed ({} in a row)", failures).red()
+                            );
+                        }
+                        if opts.fail_limit.is_some_and(|limit| failures >= limit) {
+                            log!(
+                                quiet,
+                                "{} paused after {} failures in a row — press Enter to resume watching",
+                                tag(),
+                                failures
+                            );
+                            paused = true;
+                            queued = None;
+                        }
+                    }
+                }
+                if notify
+                    && run.is_none()
+                    && let Err(e) =
+                        notify_finished(opts.task_name.as_deref().unwrap_or(run_str), ok, &summary)
+                {
+                    eprintln!(
+                        "{} desktop notifications unavailable ({}) — turning them off",
+                        "Warning:".yellow(),
+                        e
+                    );
+                    notify = false;
+                }
+            }
+        }
+
+        if run.is_none()
+            && let Some(r) = retry.take_if(|r| r.at <= Instant::now())
+        {
+            *run = spawn_run(&steps, r.step, r.changed, r.kind, opts.prefix_label())
+                .map(|new| Run { ok: r.ok, ..new });
+        }
 
-    let mut last_run = Instant::now();
-    let mut child = Some(
-        Command::new(&command.cmd)
-            .args(&command.args)
-            .spawn()
-            .expect("failed to spawn command"),
-    );
+        if run.is_none()
+            && retry.is_none()
+            && throttle_left(last_finished).is_zero()
+            && let Some(changed) = queued.take()
+        {
+            warn_storm(&mut starts, &mut storm_warned);
+            announce(&changed, &separator, opts, &mut can_clear);
+            *run = spawn_run(&steps, 0, changed, kind, opts.prefix_label());
+        }
 
-    let (reaper_tx, reaper_rx) = mpsc::channel::<Child>();
-    thread::spawn(move || {
-        while let Ok(mut c) = reaper_rx.recv() {
-            c.kill().ok();
-            c.wait().ok();
+        if let Ok(Ok(e)) = &received {
+            debug!(opts.verbose, "event {:?} {:?}", e.kind, e.paths);
         }
-    });
-    for event in rx {
-        match event {
-            Ok(e) if matches!(e.kind, EventKind::Modify(_) | EventKind::Create(_)) => {
-                if last_run.elapsed() < Duration::from_millis(debounce) {
+        let mut renamed_from = None;
+        let received = received.map(|r| {
+            r.map(|mut e| {
+                renamed_from = normalize_rename(&mut e, &targets);
+                e
+            })
+        });
+        match received {
+            Ok(Ok(mut e)) if opts.events.iter().any(|t| t.matches(&e.kind)) => {
+                if matches!(e.kind, EventKind::Remove(_)) {
+                    for p in &e.paths {
+                        hashes.remove(&hash_key(p));
+                    }
+                }
+                if opts.wait {
+                    promote_waiting(&mut waiting, watcher.as_mut(), quiet);
+                }
+                if (opts.max_depth.is_some() || opts.watch_new_dirs)
+                    && matches!(e.kind, EventKind::Create(_))
+                {
+                    for p in &e.paths {
+                        watch_new_dir(watcher.as_mut(), p, &targets, opts);
+                    }
+                }
+                // directories watched on behalf of files or missing paths
+                // report their other entries too
+                e.paths.retain(|p| {
+                    let p = std::path::absolute(p).unwrap_or_else(|_| p.clone());
+                    targets.iter().any(|t| p.starts_with(t))
+                });
+                e.paths.retain(|p| !is_excluded(p, &excludes));
+                if !opts.no_default_ignores {
+                    e.paths.retain(|p| !is_editor_temp(p));
+                }
+                if let Some(filter) = &extension_filter {
+                    e.paths.retain(|p| filter.allows(p, &targets));
+                }
+                if e.paths.is_empty() {
+                    debug!(opts.verbose, "event ignored — no watched paths left");
                     continue;
                 }
-                last_run = Instant::now();
-
-                if let Some(c) = child.take() {
-                    reaper_tx.send(c).ok();
+                debug!(
+                    opts.verbose,
+                    "debouncing — running in {} unless more changes arrive",
+                    format_duration(debounce)
+                );
+                // the source of a rename was reported on its own just before,
+                // so it mustn't stay one of the {files}
+                if let Some(from) = &renamed_from {
+                    hashes.remove(&hash_key(from));
+                    for entry in pending.values_mut() {
+                        entry.touched.remove(from);
+                    }
+                }
+                let during_run =
+                    run.is_some() || last_finished.is_some_and(|f| f.elapsed() < debounce);
+                let kind = event_kind(&e.kind);
+                let mut seen = HashSet::new();
+                for p in e.paths {
+                    let root = root_of(&p);
+                    let entry = pending.entry(root).or_insert_with(|| Pending {
+                        trigger: p.clone(),
+                        kind,
+                        first: Instant::now(),
+                        last: Instant::now(),
+                        touched: HashSet::new(),
+                        during_run,
+                    });
+                    if seen.insert(root) {
+                        entry.trigger = p.clone();
+                        entry.kind = kind;
+                    }
+                    entry.last = Instant::now();
+                    entry.touched.insert(p);
+                }
+            }
+            Ok(Ok(e)) if matches!(e.kind, EventKind::Remove(_)) => {
+                for p in &e.paths {
+                    hashes.remove(&hash_key(p));
+                }
+            }
+            // new directories past the limit go unwatched, but the rest still work
+            Ok(Err(e)) if matches!(e.kind, notify::ErrorKind::MaxFilesWatch) => {
+                if !limit_warned {
+                    eprintln!(
+                        "{} the OS file watch limit was reached — some new directories aren't watched",
+                        "Warning:".yellow()
+                    );
+                    watch_limit_hint();
+                    limit_warned = true;
+                }
+            }
+            Ok(Err(e)) => {
+                eprintln!("{} watch error: {:#?}", "Error:".red(), e);
+                emit_event(
+                    opts,
+                    LifecycleEvent::WatchError {
+                        message: e.to_string(),
+                        time: Utc::now().to_rfc3339(),
+                    },
+                );
+            }
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            // the backend dropped its sender, so no more events will arrive
+            Err(RecvTimeoutError::Disconnected) => {
+                if !restarted && let Ok((w, r)) = create_watcher(&paths, opts) {
+                    eprintln!("{} watcher stopped — restarted it", "Warning:".yellow());
+                    (watcher, rx) = (w, r);
+                    waiting = waiting_paths(&paths);
+                    restarted = true;
+                    continue;
                 }
-                let file_name = e
-                    .paths
-                    .first()
-                    .and_then(|p| p.file_name())
-                    .map(|f| f.to_string_lossy().to_string())
-                    .unwrap_or_default();
+                eprintln!(
+                    "{} watcher stopped and couldn't be restarted",
+                    "Error:".red()
+                );
+                emit_event(
+                    opts,
+                    LifecycleEvent::WatchError {
+                        message: "watcher stopped and couldn't be restarted".to_string(),
+                        time: Utc::now().to_rfc3339(),
+                    },
+                );
+                if let Some(mut r) = run.take() {
+                    stop_child(&mut r.child, kill_timeout);
+                }
+                finish(1);
+            }
+        }
 
-                if no_clear {
-                    log!(quiet, "{}", "_".repeat(width));
-                } else {
-                    clearscreen::clear().unwrap();
+        if paused {
+            pending.clear();
+            if !entered {
+                continue;
+            }
+            paused = false;
+            failures = 0;
+            log!(quiet, "{} resumed — waiting for changes", tag());
+        }
+        let ready: Vec<usize> = pending
+            .iter()
+            .filter(|(_, p)| settle_left(p).is_zero())
+            .map(|(root, _)| *root)
+            .collect();
+        let during_run = !ready.is_empty() && ready.iter().all(|root| pending[root].during_run);
+        let changed = match ready
+            .first()
+            .map(|root| (pending[root].trigger.clone(), pending[root].kind))
+        {
+            Some((trigger, trigger_kind)) => {
+                kind = Some(trigger_kind);
+                let mut touched = HashSet::new();
+                for root in ready {
+                    touched.extend(pending.remove(&root).map(|p| p.touched).unwrap_or_default());
+                }
+                if let Some(seed) = seeding.take() {
+                    // anything hashed since startup is newer than the seed
+                    for (path, hash) in seed.join().unwrap_or_default() {
+                        hashes.entry(path).or_insert(hash);
+                    }
+                }
+                // hash every touched file so each one's baseline stays current
+                if !forced
+                    && opts.skip_unchanged
+                    && touched
+                        .iter()
+                        .filter(|p| contents_changed(p, &mut hashes))
+                        .count()
+                        == 0
+                {
+                    debug!(opts.verbose, "contents unchanged — skipping");
+                    continue;
                 }
+                debug!(
+                    opts.verbose,
+                    "debounce window elapsed with {} changed file{}",
+                    touched.len(),
+                    if touched.len() == 1 { "" } else { "s" }
+                );
+
+                let mut others: Vec<PathBuf> =
+                    touched.into_iter().filter(|p| *p != trigger).collect();
+                others.sort();
+                std::iter::once(trigger).chain(others).collect()
+            }
+            // a rerun asked for from the keyboard, with no file behind it
+            None if forced => Vec::new(),
+            None => continue,
+        };
+
+        if during_run && !forced {
+            self_triggered += 1;
+            if self_triggered == SELF_TRIGGER_RUNS {
+                warn_self_trigger(&changed);
+            }
+        } else {
+            self_triggered = 0;
+        }
+
+        if opts.queue && run.is_some() {
+            if queued.is_none() {
                 log!(
                     quiet,
-                    "{} {} changed at {}",
-                    CUE.green(),
-                    file_name.cyan(),
-                    Utc::now().format("%H:%M:%S")
+                    "{} change queued — will rerun when the current run finishes",
+                    tag()
                 );
-                log!(quiet, "{}", "_".repeat(width));
+            }
+            queue_changes(&mut queued, changed);
+            continue;
+        }
 
-                child = Some(
-                    Command::new(&command.cmd)
-                        .args(&command.args)
-                        .spawn()
-                        .expect("failed to spawn command"),
+        retry = None;
+        attempts = 0;
+        if let Some(mut r) = run.take() {
+            let elapsed = r.started.elapsed();
+            let status = stop_child(&mut r.child, kill_timeout);
+            run_end(opts, status, elapsed);
+            let mut session = SESSION.lock().unwrap();
+            session.killed += 1;
+            session.busy += elapsed;
+            drop(session);
+            last_finished = Some(Instant::now());
+            log!(
+                quiet,
+                "{} {}",
+                tag(),
+                format!("killed after {}", format_duration(elapsed)).yellow()
+            );
+        }
+        let left = throttle_left(last_finished);
+        if !left.is_zero() {
+            if queued.is_none() {
+                log!(
+                    quiet,
+                    "{} throttled — next run in {}",
+                    tag(),
+                    format_duration(left)
                 );
             }
-            Err(e) => eprintln!("{} watch error: {:#?}", "Error:".red(), e),
-            _ => {}
+            queue_changes(&mut queued, changed);
+            continue;
         }
+        warn_storm(&mut starts, &mut storm_warned);
+        announce(&changed, &separator, opts, &mut can_clear);
+        *run = spawn_run(&steps, 0, changed, kind, opts.prefix_label());
     }
+}
 
-    Ok(())
+// printed on the way out: `12 runs — 9 passed, 3 failed, 2 restarted — 63.2s running`
+fn print_session(tag: &ColoredString) {
+    let Ok(session) = SESSION.lock() else {
+        return;
+    };
+    let runs = session.passed + session.failed;
+    let mut parts = vec![
+        format!("{} passed", session.passed).green().to_string(),
+        format!("{} failed", session.failed).red().to_string(),
+    ];
+    if session.killed > 0 {
+        parts.push(format!("{} restarted", session.killed).yellow().to_string());
+    }
+    if !session.quiet {
+        // ^C leaves the cursor mid-line
+        println!();
+    }
+    log!(
+        session.quiet,
+        "{} {} run{} — {} — {} running",
+        tag,
+        runs,
+        if runs == 1 { "" } else { "s" },
+        parts.join(", "),
+        format_duration(session.busy)
+    );
+}
+
+fn stop_all(kill_timeout: Duration) {
+    for current in RUNNING.lock().unwrap().iter() {
+        if let Some(mut r) = current.lock().ok().and_then(|mut run| run.take()) {
+            stop_child(&mut r.child, kill_timeout);
+        }
+    }
+}
+
+// one reader for every watcher: any line resumes a --fail-limit pause, and
+// with hotkeys on `r` reruns and `q` quits
+fn listen_stdin(hotkeys: bool, kill_timeout: Duration) {
+    static LISTENER: Once = Once::new();
+    LISTENER.call_once(|| {
+        let tag = tag();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                let rerun = match line.trim() {
+                    "r" if hotkeys => true,
+                    "q" if hotkeys => {
+                        print_session(&tag);
+                        stop_all(kill_timeout);
+                        finish(0);
+                    }
+                    _ => false,
+                };
+                let message = || {
+                    if rerun {
+                        Message::Rerun
+                    } else {
+                        Message::Enter
+                    }
+                };
+                // a loop that has exited leaves its sender behind
+                STDIN_LISTENERS
+                    .lock()
+                    .unwrap()
+                    .retain(|tx| tx.send(message()).is_ok());
+            }
+        });
+    });
+}
+
+fn queue_changes(queued: &mut Option<Vec<PathBuf>>, changed: Vec<PathBuf>) {
+    let queue = queued.get_or_insert_with(Vec::new);
+    for p in changed {
+        if !queue.contains(&p) {
+            queue.push(p);
+        }
+    }
+}
+
+// the same loop caught sooner: runs that keep being triggered by changes
+// made while the previous one was still going
+fn warn_self_trigger(changed: &[PathBuf]) {
+    let mut shown: Vec<String> = changed
+        .iter()
+        .take(3)
+        .map(|p| p.display().to_string())
+        .collect();
+    if changed.len() > shown.len() {
+        shown.push(format!("and {} more", changed.len() - shown.len()));
+    }
+    eprintln!(
+        "{} the last {} runs were each triggered by changes made while the command was running ({}) — if the command writes into a watched path, add an `exclude` for it",
+        "Warning:".yellow().bold(),
+        SELF_TRIGGER_RUNS,
+        shown.join(", ")
+    );
+}
+
+// a command that writes into its own watched paths retriggers itself forever
+fn warn_storm(starts: &mut VecDeque<Instant>, warned: &mut bool) {
+    let window = Duration::from_millis(STORM_WINDOW_MS);
+    starts.push_back(Instant::now());
+    while starts.front().is_some_and(|s| s.elapsed() > window) {
+        starts.pop_front();
+    }
+    if starts.len() <= STORM_RUNS {
+        *warned = false;
+    } else if !*warned {
+        eprintln!(
+            "{} {} runs in {} — if the command writes into a watched path, add an `exclude` for it or use --min-interval",
+            "Warning:".yellow(),
+            starts.len(),
+            format_duration(window)
+        );
+        *warned = true;
+    }
+}
+
+// a terminal's width can't be detected when output is piped, so fall back
+// to 80 columns there
+fn separator_line(opts: &WatchOptions) -> String {
+    let columns = terminal_size().map_or(80, |(Width(w), _)| w as usize);
+    let width = match opts.separator_width {
+        SeparatorWidth::Full => columns,
+        SeparatorWidth::Half => columns / 2,
+        SeparatorWidth::Columns(n) => n,
+    };
+    opts.separator.to_string().repeat(width)
+}
+
+// separators only break up the terminal, so they stay out of the log file
+fn print_separator(separator: &str, quiet: bool) {
+    if !quiet {
+        println!("{}", separator);
+    }
+}
+
+fn announce(changed: &[PathBuf], separator: &str, opts: &WatchOptions, can_clear: &mut bool) {
+    let quiet = opts.quiet;
+    let file_name = changed
+        .first()
+        .and_then(|p| p.file_name())
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    if opts.scroll && !opts.no_clear {
+        // push the last run's output off screen but keep it in scrollback
+        let height = terminal_size().map_or(24, |(_, Height(h))| h as usize);
+        print!("{}", "\n".repeat(height));
+        print_separator(separator, quiet);
+    } else if opts.no_clear || !*can_clear {
+        print_separator(separator, quiet);
+    } else if let Err(e) = clearscreen::clear() {
+        eprintln!(
+            "{} can't clear the screen ({}) — printing separators instead",
+            "Warning:".yellow(),
+            e
+        );
+        *can_clear = false;
+        print_separator(separator, quiet);
+    }
+    let at = match opts.timestamp.unwrap_or(Timestamp::Utc) {
+        Timestamp::Utc => format!(" at {}", Utc::now().format(&opts.time_format)),
+        Timestamp::Local => format!(" at {}", Local::now().format(&opts.time_format)),
+        Timestamp::Off => String::new(),
+    };
+    if changed.is_empty() {
+        log!(quiet, "{} rerun requested{}", tag(), at);
+    } else {
+        log!(quiet, "{} {} changed{}", tag(), file_name.cyan(), at);
+    }
+    emit_event(
+        opts,
+        LifecycleEvent::RunStart {
+            task: opts.task_name.as_deref(),
+            files: changed,
+            time: Utc::now().to_rfc3339(),
+        },
+    );
+    print_separator(separator, quiet);
+}
+
+const TEMPLATES: &[&str] = &[
+    "rust", "node", "python", "go", "c", "cpp", "ruby", "php", "java", "kotlin", "swift", "zig",
+    "elixir", "haskell", "css", "lua", "shell",
+];
+// files that give a project's language away, in the order they're offered
+const TEMPLATE_MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "rust"),
+    ("package.json", "node"),
+    ("go.mod", "go"),
+    ("pyproject.toml", "python"),
+    ("requirements.txt", "python"),
+    ("setup.py", "python"),
+    ("Gemfile", "ruby"),
+    ("composer.json", "php"),
+    ("pom.xml", "java"),
+    ("build.gradle.kts", "kotlin"),
+    ("build.gradle", "java"),
+    ("Package.swift", "swift"),
+    ("build.zig", "zig"),
+    ("mix.exs", "elixir"),
+    ("stack.yaml", "haskell"),
+    ("CMakeLists.txt", "cpp"),
+];
+const DEFAULT_TEMPLATE: &[u8] = b"# optional: runs automatically in zero-config mode\n# default = \"build\"\n\n[tasks.build]\nwatch = [\"src\"]\nrun = \"your command here\"\n";
+
+// one entry per template, with the first marker file that pointed to it
+fn detect_templates() -> Vec<(&'static str, &'static str)> {
+    let mut found: Vec<(&str, &str)> = Vec::new();
+    for &(marker, template) in TEMPLATE_MARKERS {
+        if Path::new(marker).exists() && !found.iter().any(|(_, t)| *t == template) {
+            found.push((marker, template));
+        }
+    }
+    found
+}
+
+// returns the template and why it was picked
+fn pick_template() -> (&'static [u8], String) {
+    let found = detect_templates();
+    let choice = match found.len() {
+        0 => {
+            return (
+                DEFAULT_TEMPLATE,
+                "no project files found — using the blank template".to_string(),
+            );
+        }
+        1 => 0,
+        _ if !std::io::stdin().is_terminal() => 0,
+        _ => {
+            let items: Vec<String> = found
+                .iter()
+                .map(|(marker, template)| format!("{} (found {})", template, marker))
+                .collect();
+            dialoguer::Select::new()
+                .with_prompt("several kinds of project found — which template?")
+                .items(&items)
+                .default(0)
+                .interact()
+                .unwrap_or_else(|_| {
+                    eprintln!("{} cancelled", "Error:".red());
+                    finish(1);
+                })
+        }
+    };
+    let (marker, template) = found[choice];
+    (
+        template_for(template).unwrap_or(DEFAULT_TEMPLATE),
+        format!("found {} — using the {} template", marker, template),
+    )
+}
+
+fn template_for(name: &str) -> Option<&'static [u8]> {
+    let template: &[u8] = match name.to_lowercase().as_str() {
+        "rust" => b"default = \"run\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"rs\"]\nrun = \"cargo run\"\n[tasks.test]\nwatch = [\"src\", \"tests\"]\nextensions = [\"rs\"]\nrun = \"cargo test\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"rs\"]\nrun = \"cargo build --release\"\n[tasks.check]\nwatch = [\"src\"]\nextensions = [\"rs\"]\nrun = \"cargo check\"\n[tasks.lint]\nwatch = [\"src\"]\nextensions = [\"rs\"]\nrun = \"cargo clippy\"",
+        "node" | "nodejs" => b"default = \"dev\"\n[tasks.dev]\nwatch = [\"src\"]\nextensions = [\"js\", \"ts\"]\nrun = \"node index.js\"\n[tasks.test]\nwatch = [\"src\", \"tests\"]\nextensions = [\"js\", \"ts\"]\nrun = \"npm test\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"ts\"]\nrun = \"tsc\"\n[tasks.lint]\nwatch = [\"src\"]\nextensions = [\"js\", \"ts\"]\nrun = \"eslint src\"\n[tasks.format]\nwatch = [\"src\"]\nextensions = [\"js\", \"ts\"]\nrun = \"prettier --write src\"",
+        "python" | "py" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"py\"]\nrun = \"python main.py\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"py\"]\nrun = \"pytest\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"py\"]\nrun = \"ruff check .\"\n[tasks.format]\nwatch = [\".\"]\nextensions = [\"py\"]\nrun = \"black .\"",
+        "go" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"go run .\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"go test ./...\"\n[tasks.build]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"go build -o app .\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"golangci-lint run\"\n[tasks.fmt]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"gofmt -w .\"",
+        "c" => b"default = \"build\"\n[tasks.build]\nwatch = [\"src\", \"include\"]\nextensions = [\"c\", \"h\"]\nrun = \"gcc src/*.c -Iinclude -o app\"\n[tasks.run]\nwatch = [\"src\", \"include\"]\nextensions = [\"c\", \"h\"]\nrun = \"make && ./app\"\n[tasks.clean]\nwatch = [\"src\"]\nextensions = [\"c\", \"h\"]\nrun = \"make clean\"",
+        "cpp" => b"default = \"build\"\n[tasks.build]\nwatch = [\"src\", \"include\"]\nextensions = [\"cpp\", \"hpp\", \"h\"]\nrun = \"g++ src/*.cpp -Iinclude -o app\"\n[tasks.run]\nwatch = [\"src\", \"include\"]\nextensions = [\"cpp\", \"hpp\", \"h\"]\nrun = \"make && ./app\"\n[tasks.test]\nwatch = [\"src\", \"tests\"]\nextensions = [\"cpp\", \"hpp\"]\nrun = \"ctest --output-on-failure\"",
+        "ruby" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"rb\"]\nrun = \"ruby main.rb\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"rb\"]\nrun = \"bundle exec rspec\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"rb\"]\nrun = \"rubocop\"",
+        "php" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"php\"]\nrun = \"php index.php\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"php\"]\nrun = \"phpunit\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"php\"]\nrun = \"php -l index.php\"",
+        "java" => b"default = \"build\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"java\"]\nrun = \"javac src/*.java -d out\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"java\"]\nrun = \"java -cp out Main\"\n[tasks.test]\nwatch = [\"src\", \"test\"]\nextensions = [\"java\"]\nrun = \"mvn test\"",
+        "kotlin" => b"default = \"run\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"kt\"]\nrun = \"kotlinc src/*.kt -include-runtime -d app.jar && java -jar app.jar\"\n[tasks.test]\nwatch = [\"src\", \"test\"]\nextensions = [\"kt\"]\nrun = \"gradle test\"",
+        "swift" => b"default = \"run\"\n[tasks.run]\nwatch = [\"Sources\"]\nextensions = [\"swift\"]\nrun = \"swift run\"\n[tasks.test]\nwatch = [\"Sources\", \"Tests\"]\nextensions = [\"swift\"]\nrun = \"swift test\"\n[tasks.build]\nwatch = [\"Sources\"]\nextensions = [\"swift\"]\nrun = \"swift build\"",
+        "zig" => b"default = \"run\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"zig\"]\nrun = \"zig run src/main.zig\"\n[tasks.test]\nwatch = [\"src\"]\nextensions = [\"zig\"]\nrun = \"zig test src/main.zig\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"zig\"]\nrun = \"zig build\"",
+        "elixir" => b"default = \"run\"\n[tasks.run]\nwatch = [\"lib\"]\nextensions = [\"ex\", \"exs\"]\nrun = \"mix run\"\n[tasks.test]\nwatch = [\"lib\", \"test\"]\nextensions = [\"ex\", \"exs\"]\nrun = \"mix test\"\n[tasks.compile]\nwatch = [\"lib\"]\nextensions = [\"ex\"]\nrun = \"mix compile\"",
+        "haskell" => b"default = \"run\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"hs\"]\nrun = \"cabal run\"\n[tasks.test]\nwatch = [\"src\", \"test\"]\nextensions = [\"hs\"]\nrun = \"cabal test\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"hs\"]\nrun = \"cabal build\"",
+        "css" | "scss" => b"default = \"build\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"scss\", \"sass\"]\nrun = \"sass src/main.scss dist/style.css\"\n[tasks.watch]\nwatch = [\"src\"]\nextensions = [\"css\", \"scss\"]\nrun = \"sass --watch src:dist\"",
+        "lua" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"lua\"]\nrun = \"lua main.lua\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"lua\"]\nrun = \"busted\"",
+        "shell" | "sh" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"sh\"]\nrun = \"bash main.sh\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"sh\"]\nrun = \"shellcheck *.sh\"",
+        _ => return None,
+    };
+    Some(template)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
+    let mut args = Cli::parse();
+    let invoked_from = std::env::current_dir().ok();
+    colored::control::set_override(match args.color {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                && std::io::stdout().is_terminal()
+        }
+    });
+
+    // before anything else, so config lookup starts from there
+    if let Some(dir) = &args.working_dir
+        && let Err(e) = std::env::set_current_dir(dir)
+    {
+        eprintln!(
+            "{} can't change to directory '{}': {}",
+            "Error:".red(),
+            dir.display(),
+            e
+        );
+        finish(1);
+    }
+
+    let watch_opts = match &args.command {
+        Some(Commands::Run { opts, .. }) => Some(&**opts),
+        None => Some(&args.opts),
+        _ => None,
+    };
+    if let Some(opts) = watch_opts
+        && opts.daemon
+    {
+        daemonize(opts.log_file.as_deref(), invoked_from);
+    }
 
     match args.command {
         Some(Commands::Task { action }) => {
-            let mut config: CueConfig = load_config(true);
+            let config: CueConfig = load_config(None);
+            let (path, mut doc) = load_config_document();
+            let tasks = tasks_table(&mut doc);
             match action {
                 TaskAction::Add {
                     name,
                     watch,
                     run,
                     extensions,
+                    description,
+                    strict,
                 } => {
-                    config.tasks.insert(
-                        name.clone(),
-                        Task {
-                            watch,
-                            run: Some(run),
-                            extensions,
-                        },
-                    );
-                    confy::store("cue", None, config)?;
-                    println!("{} task '{}' saved", CUE.green(), name);
+                    let task = Task {
+                        description,
+                        watch,
+                        watch_shallow: None,
+                        run: Some(run.into()),
+                        cmd: None,
+                        args: None,
+                        extensions,
+                        shell: None,
+                        ignore: None,
+                        exclude: None,
+                        run_on_start: None,
+                        before: None,
+                        after: None,
+                        env_file: None,
+                        env: None,
+                        timestamp: None,
+                        timeout: None,
+                        debounce: None,
+                        quiet: None,
+                        no_clear: None,
+                        deps: None,
+                        color: None,
+                        source: None,
+                    };
+                    check_task(&name, &task, strict);
+                    tasks.insert(&name, task_item(&task));
+                    store_config_document(&path, &doc)?;
+                    println!("{} task '{}' saved", tag(), name);
                 }
-                TaskAction::Remove { name } => {
-                    if config.tasks.remove(&name).is_some() {
-                        confy::store("cue", None, config)?;
-                        println!("{} task '{}' removed", CUE.green(), name);
-                    } else {
+                TaskAction::Remove { name, yes } => {
+                    let Some(task) = config.tasks.get(&name) else {
                         eprintln!("{} task '{}' not found", "Error:".red(), name);
-                        process::exit(1);
+                        finish(1);
+                    };
+                    if !yes {
+                        if !std::io::stdout().is_terminal() {
+                            eprintln!(
+                                "{} not a terminal — pass --yes to remove task '{}'",
+                                "Error:".red(),
+                                name
+                            );
+                            finish(1);
+                        }
+                        print_task(&name, task, is_default(&config, &name));
+                        let confirmed = Confirm::new()
+                            .with_prompt(format!("remove task '{}'?", name))
+                            .default(false)
+                            .interact()
+                            .unwrap_or(false);
+                        if !confirmed {
+                            println!("{} task '{}' kept", tag(), name);
+                            return Ok(());
+                        }
                     }
+                    tasks.remove(&name);
+                    store_config_document(&path, &doc)?;
+                    // a task added again later under this name starts fresh
+                    if let Some(source) = &task.source
+                        && load_state()
+                            .get(source)
+                            .is_some_and(|runs| runs.contains_key(&name))
+                    {
+                        update_state(|state| {
+                            if let Some(runs) = state.get_mut(source) {
+                                runs.remove(&name);
+                            }
+                        });
+                    }
+                    println!("{} task '{}' removed", tag(), name);
                 }
-                TaskAction::List => {
-                    if config.tasks.is_empty() {
-                        println!("{} no saved tasks", CUE.green());
+                TaskAction::List { json, names_only } => {
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&config)?);
+                    } else if names_only {
+                        for name in config.tasks.keys() {
+                            println!("{}", name);
+                        }
+                    } else if config.tasks.is_empty() {
+                        println!("{} no saved tasks", tag());
                     } else {
-                        println!("{} saved tasks:", CUE.green());
+                        println!("{} saved tasks:", tag());
+                        let state = load_state();
                         for (name, task) in &config.tasks {
-                            println!(
-                                "  {} — watch: {:?} | extensions: {:?} | run: \"{}\"",
-                                name.cyan(),
+                            let mut details = format!(
+                                "watch: {:?} | extensions: {:?} | run: \"{}\"",
                                 task.watch,
                                 task.extensions,
-                                task.run.as_deref().unwrap_or("none")
+                                task.runs()
+                                    .map(|runs| runs.join("\", \""))
+                                    .unwrap_or_else(|| "none".to_string())
                             );
+                            if let Some(last) = task
+                                .source
+                                .as_ref()
+                                .and_then(|source| state.get(source))
+                                .and_then(|runs| runs.get(name))
+                            {
+                                details = format!("{} | {}", details, last_run_summary(last));
+                            }
+                            // a description gets the name's line, the details go below it
+                            match &task.description {
+                                Some(description) => println!(
+                                    "  {} — {}\n      {}",
+                                    name.cyan(),
+                                    description,
+                                    details
+                                ),
+                                None => println!("  {} — {}", name.cyan(), details),
+                            }
                         }
                     }
                 }
+                TaskAction::Show { name } => {
+                    let task = config.tasks.get(&name).unwrap_or_else(|| {
+                        eprintln!("{} task '{}' not found", "Error:".red(), name);
+                        finish(1);
+                    });
+                    print_task(&name, task, is_default(&config, &name));
+                }
                 TaskAction::Edit {
                     name,
                     watch,
                     run,
                     extensions,
+                    description,
+                    strict,
                 } => {
-                    let task = config.tasks.get_mut(&name).unwrap_or_else(|| {
-                        eprintln!("{} task '{}' not found", "Error:".red(), name);
-                        process::exit(1);
-                    });
+                    if let Some(current) = config.tasks.get(&name) {
+                        let mut edited = current.clone();
+                        if let Some(x) = &run {
+                            edited.run = Some(x.clone().into());
+                        }
+                        if !watch.is_empty() {
+                            edited.watch = watch.clone();
+                        }
+                        check_task(&name, &edited, strict);
+                    }
+                    let task = tasks
+                        .get_mut(&name)
+                        .and_then(Item::as_table_like_mut)
+                        .unwrap_or_else(|| {
+                            eprintln!("{} task '{}' not found", "Error:".red(), name);
+                            finish(1);
+                        });
                     if let Some(x) = run {
-                        task.run = Some(x);
+                        set_field(task, "run", run_item(x));
                     }
                     if let Some(x) = extensions {
-                        task.extensions = Some(x);
+                        set_field(task, "extensions", strings_item(x));
                     }
                     if !watch.is_empty() {
-                        task.watch = watch;
+                        set_field(task, "watch", strings_item(watch));
+                    }
+                    if let Some(x) = description {
+                        set_field(task, "description", value(x));
                     }
-                    confy::store("cue", None, config)?;
-                    println!("{} task '{}' updated", CUE.green(), name);
+                    store_config_document(&path, &doc)?;
+                    println!("{} task '{}' updated", tag(), name);
                 }
                 TaskAction::Rename { name, new_name } => {
-                    let task = config.tasks.remove(&name).unwrap_or_else(|| {
+                    let task = tasks.remove(&name).unwrap_or_else(|| {
+                        eprintln!("{} task '{}' not found", "Error:".red(), name);
+                        finish(1);
+                    });
+                    tasks.insert(&new_name, task);
+                    store_config_document(&path, &doc)?;
+                    println!("{} task '{}' renamed to '{}'", tag(), name, new_name);
+                }
+                TaskAction::Copy { name, new_name } => {
+                    let task = config.tasks.get(&name).unwrap_or_else(|| {
                         eprintln!("{} task '{}' not found", "Error:".red(), name);
-                        process::exit(1);
+                        finish(1);
                     });
-                    config.tasks.insert(new_name.clone(), task);
-                    confy::store("cue", None, config)?;
-                    println!("{} task '{}' renamed to '{}'", CUE.green(), name, new_name);
+                    if tasks.contains_key(&new_name) {
+                        eprintln!("{} task '{}' already exists", "Error:".red(), new_name);
+                        finish(1);
+                    }
+                    tasks.insert(&new_name, task_item(task));
+                    store_config_document(&path, &doc)?;
+                    println!("{} task '{}' copied to '{}'", tag(), name, new_name);
+                }
+                TaskAction::Export { path: target } => match target {
+                    Some(target) => {
+                        fs::write(&target, doc.to_string())?;
+                        println!(
+                            "{} {} task{} exported to '{}'",
+                            tag(),
+                            config.tasks.len(),
+                            if config.tasks.len() == 1 { "" } else { "s" },
+                            target.display()
+                        );
+                    }
+                    None => print!("{}", doc),
+                },
+                TaskAction::Import {
+                    path: source,
+                    overwrite,
+                } => {
+                    // the file's [defaults] go into each task, which won't
+                    // have them to fall back on in the global config
+                    let imported = load_config(Some(&source)).into_resolved_tasks();
+                    let mut count = 0;
+                    for (name, task) in &imported {
+                        if tasks.contains_key(name) && !overwrite {
+                            println!(
+                                "{} skipped '{}' — it already exists (use --overwrite to replace it)",
+                                tag(),
+                                name
+                            );
+                            continue;
+                        }
+                        tasks.insert(name, task_item(task));
+                        count += 1;
+                    }
+                    store_config_document(&path, &doc)?;
+                    println!(
+                        "{} {} task{} imported from '{}'",
+                        tag(),
+                        count,
+                        if count == 1 { "" } else { "s" },
+                        source.display()
+                    );
                 }
             }
         }
 
         Some(Commands::Run {
-            name,
+            names,
             watch,
             run,
             extensions,
-            debounce,
             global,
-            quiet,
-            no_clear,
+            mut opts,
+            extra_args,
         }) => {
-            let config = resolve_config(global, quiet);
-            run_task(
-                &config, name, watch, run, extensions, debounce, quiet, no_clear,
-            )?;
+            opts.workdir = invocation_dir(&opts);
+            opts.extra_args = extra_args;
+            setup_output(&mut opts);
+            let config = resolve_config(
+                global,
+                args.config.as_deref(),
+                !args.no_merge,
+                opts.quiet,
+                opts.verbose,
+            );
+            let names = if names.is_empty() {
+                several_defaults(&config, opts.quiet).unwrap_or_default()
+            } else {
+                names
+            };
+            if names.len() > 1 {
+                if watch.is_some() || run.is_some() || extensions.is_some() {
+                    eprintln!(
+                        "{} -w, -r and -e can't be used when running several tasks",
+                        "Error:".red()
+                    );
+                    finish(1);
+                }
+                finish(run_tasks(&config, names, &opts));
+            }
+            let name = names.into_iter().next();
+            let code = or_finish(run_task(&config, name, watch, run, extensions, &opts));
+            finish(code);
         }
 
         None => {
+            setup_output(&mut args.opts);
             if args.watch.is_empty() && args.run.is_none() && args.extensions.is_none() {
-                let config = if args.global {
-                    log!(args.quiet, "{} loading global tasks", CUE.green());
-                    load_config(true)
-                } else if Path::new("cue.toml").exists() {
-                    log!(args.quiet, "{} loading tasks from 'cue.toml'", CUE.green());
-                    load_config(false)
-                } else {
+                args.opts.workdir = invocation_dir(&args.opts);
+                if args.config.is_none() && !args.global && find_local_config().is_none() {
                     eprintln!(
                         "{} no 'cue.toml' found — use -w/-e and -r to watch directly, or -g for global tasks",
                         "Error:".red()
                     );
-                    process::exit(1);
-                };
-                run_task(
-                    &config,
-                    None,
-                    None,
-                    None,
-                    None,
-                    args.debounce,
-                    args.quiet,
-                    args.no_clear,
-                )?;
+                    finish(1);
+                }
+                let config = resolve_config(
+                    args.global,
+                    args.config.as_deref(),
+                    !args.no_merge,
+                    args.opts.quiet,
+                    args.opts.verbose,
+                );
+                if let Some(names) = several_defaults(&config, args.opts.quiet) {
+                    finish(run_tasks(&config, names, &args.opts));
+                }
+                let code = or_finish(run_task(&config, None, None, None, None, &args.opts));
+                finish(code);
             } else {
                 if args.watch.is_empty() && args.extensions.is_none() {
                     eprintln!(
                         "{} please provide paths with -w or extensions with -e",
                         "Error:".red()
                     );
-                    process::exit(1);
+                    finish(1);
                 }
-                let run_str = args.run.unwrap_or_else(|| {
+                let runs = args.run.unwrap_or_else(|| {
                     eprintln!("{} please provide a command with -r", "Error:".red());
-                    process::exit(1);
+                    finish(1);
                 });
-                let watch_strs = resolve_paths(args.watch, args.extensions);
-                let paths: Vec<&Path> = watch_strs.iter().map(|s| Path::new(s)).collect();
-                let command = parse_command(&run_str);
-                validate_paths(&paths, args.quiet);
-                validate_command(&command, args.quiet);
-                start_watcher(
+                args.opts.extensions = args.extensions.clone().unwrap_or_default();
+                let (mut watch_strs, shallow) =
+                    resolve_paths(args.watch, args.extensions, &mut args.opts);
+                args.opts.shallow = shallow
+                    .iter()
+                    .map(|p| canonical_path(Path::new(p)))
+                    .collect();
+                watch_strs.extend(shallow);
+                let paths: Vec<&Path> = watch_strs.iter().map(Path::new).collect();
+                let steps = plan_steps(
+                    &[],
+                    &runs,
+                    &[],
+                    args.opts.shell,
+                    &load_env_file(args.opts.env_file.as_deref()),
+                );
+                validate_paths(&paths, args.opts.wait, args.opts.quiet);
+                validate_commands(&steps, args.opts.quiet);
+                if args.opts.dry_run {
+                    dry_run(&paths, &steps, &[]);
+                    finish(0);
+                }
+                write_pid_file(&args.opts);
+                if args.opts.once {
+                    run_once(&steps, &args.opts);
+                }
+                or_finish(start_watcher(
                     paths,
-                    command,
-                    &run_str,
-                    args.debounce,
-                    args.quiet,
-                    args.no_clear,
-                )?;
+                    steps,
+                    &runs.join("' then '"),
+                    &args.opts,
+                ));
             }
         }
 
-        Some(Commands::Init { template }) => {
-            let template: &[u8] = match template {
-    None => b"# optional: runs automatically in zero-config mode\n# default = \"build\"\n\n[tasks.build]\nwatch = [\"src\"]\nrun = \"your command here\"\n",
-    Some(x) => match x.to_lowercase().as_str() {
-        "rust" => b"default = \"run\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"rs\"]\nrun = \"cargo run\"\n[tasks.test]\nwatch = [\"src\", \"tests\"]\nextensions = [\"rs\"]\nrun = \"cargo test\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"rs\"]\nrun = \"cargo build --release\"\n[tasks.check]\nwatch = [\"src\"]\nextensions = [\"rs\"]\nrun = \"cargo check\"\n[tasks.lint]\nwatch = [\"src\"]\nextensions = [\"rs\"]\nrun = \"cargo clippy\"",
-        "node" | "nodejs" => b"default = \"dev\"\n[tasks.dev]\nwatch = [\"src\"]\nextensions = [\"js\", \"ts\"]\nrun = \"node index.js\"\n[tasks.test]\nwatch = [\"src\", \"tests\"]\nextensions = [\"js\", \"ts\"]\nrun = \"npm test\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"ts\"]\nrun = \"tsc\"\n[tasks.lint]\nwatch = [\"src\"]\nextensions = [\"js\", \"ts\"]\nrun = \"eslint src\"\n[tasks.format]\nwatch = [\"src\"]\nextensions = [\"js\", \"ts\"]\nrun = \"prettier --write src\"",
-        "go" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"go run .\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"go test ./...\"\n[tasks.build]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"go build -o app .\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"golangci-lint run\"\n[tasks.fmt]\nwatch = [\".\"]\nextensions = [\"go\"]\nrun = \"gofmt -w .\"",
-        "c" => b"default = \"build\"\n[tasks.build]\nwatch = [\"src\", \"include\"]\nextensions = [\"c\", \"h\"]\nrun = \"gcc src/*.c -Iinclude -o app\"\n[tasks.run]\nwatch = [\"src\", \"include\"]\nextensions = [\"c\", \"h\"]\nrun = \"make && ./app\"\n[tasks.clean]\nwatch = [\"src\"]\nextensions = [\"c\", \"h\"]\nrun = \"make clean\"",
-        "cpp" => b"default = \"build\"\n[tasks.build]\nwatch = [\"src\", \"include\"]\nextensions = [\"cpp\", \"hpp\", \"h\"]\nrun = \"g++ src/*.cpp -Iinclude -o app\"\n[tasks.run]\nwatch = [\"src\", \"include\"]\nextensions = [\"cpp\", \"hpp\", \"h\"]\nrun = \"make && ./app\"\n[tasks.test]\nwatch = [\"src\", \"tests\"]\nextensions = [\"cpp\", \"hpp\"]\nrun = \"ctest --output-on-failure\"",
-        "ruby" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"rb\"]\nrun = \"ruby main.rb\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"rb\"]\nrun = \"bundle exec rspec\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"rb\"]\nrun = \"rubocop\"",
-        "php" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"php\"]\nrun = \"php index.php\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"php\"]\nrun = \"phpunit\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"php\"]\nrun = \"php -l index.php\"",
-        "java" => b"default = \"build\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"java\"]\nrun = \"javac src/*.java -d out\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"java\"]\nrun = \"java -cp out Main\"\n[tasks.test]\nwatch = [\"src\", \"test\"]\nextensions = [\"java\"]\nrun = \"mvn test\"",
-        "kotlin" => b"default = \"run\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"kt\"]\nrun = \"kotlinc src/*.kt -include-runtime -d app.jar && java -jar app.jar\"\n[tasks.test]\nwatch = [\"src\", \"test\"]\nextensions = [\"kt\"]\nrun = \"gradle test\"",
-        "swift" => b"default = \"run\"\n[tasks.run]\nwatch = [\"Sources\"]\nextensions = [\"swift\"]\nrun = \"swift run\"\n[tasks.test]\nwatch = [\"Sources\", \"Tests\"]\nextensions = [\"swift\"]\nrun = \"swift test\"\n[tasks.build]\nwatch = [\"Sources\"]\nextensions = [\"swift\"]\nrun = \"swift build\"",
-        "zig" => b"default = \"run\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"zig\"]\nrun = \"zig run src/main.zig\"\n[tasks.test]\nwatch = [\"src\"]\nextensions = [\"zig\"]\nrun = \"zig test src/main.zig\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"zig\"]\nrun = \"zig build\"",
-        "elixir" => b"default = \"run\"\n[tasks.run]\nwatch = [\"lib\"]\nextensions = [\"ex\", \"exs\"]\nrun = \"mix run\"\n[tasks.test]\nwatch = [\"lib\", \"test\"]\nextensions = [\"ex\", \"exs\"]\nrun = \"mix test\"\n[tasks.compile]\nwatch = [\"lib\"]\nextensions = [\"ex\"]\nrun = \"mix compile\"",
-        "haskell" => b"default = \"run\"\n[tasks.run]\nwatch = [\"src\"]\nextensions = [\"hs\"]\nrun = \"cabal run\"\n[tasks.test]\nwatch = [\"src\", \"test\"]\nextensions = [\"hs\"]\nrun = \"cabal test\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"hs\"]\nrun = \"cabal build\"",
-        "css" | "scss" => b"default = \"build\"\n[tasks.build]\nwatch = [\"src\"]\nextensions = [\"scss\", \"sass\"]\nrun = \"sass src/main.scss dist/style.css\"\n[tasks.watch]\nwatch = [\"src\"]\nextensions = [\"css\", \"scss\"]\nrun = \"sass --watch src:dist\"",
-        "lua" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"lua\"]\nrun = \"lua main.lua\"\n[tasks.test]\nwatch = [\".\"]\nextensions = [\"lua\"]\nrun = \"busted\"",
-        "shell" | "sh" => b"default = \"run\"\n[tasks.run]\nwatch = [\".\"]\nextensions = [\"sh\"]\nrun = \"bash main.sh\"\n[tasks.lint]\nwatch = [\".\"]\nextensions = [\"sh\"]\nrun = \"shellcheck *.sh\"",
-        _ => b"# optional: runs automatically in zero-config mode\n# default = \"build\"\n\n[tasks.build]\nwatch = [\"src\"]\nrun = \"your command here\"\n"
-    },
-};
+        Some(Commands::Validate { global }) => {
+            let config = resolve_config(global, args.config.as_deref(), !args.no_merge, false, 0);
+            let problems = validate_config(&config);
+            if problems > 0 {
+                eprintln!(
+                    "{} {} problem{} found",
+                    "Error:".red(),
+                    problems,
+                    if problems == 1 { "" } else { "s" }
+                );
+                finish(1);
+            }
+            println!("{} all tasks are valid", tag());
+        }
+
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "cue", &mut std::io::stdout());
+        }
+
+        Some(Commands::Stop { pid_file }) => stop_running(&pid_file),
+
+        Some(Commands::Where { global }) => {
+            let path = match args.config {
+                Some(path) => std::path::absolute(path)?,
+                None => match find_local_config().filter(|_| !global) {
+                    Some(path) => path,
+                    None => confy::get_configuration_file_path("cue", None)?,
+                },
+            };
+            println!("{}", path.display());
+        }
+
+        Some(Commands::Init {
+            template,
+            list,
+            force,
+            stdout,
+        }) => {
+            if list {
+                println!("{} available templates:", tag());
+                for name in TEMPLATES.iter().filter(|name| template_for(name).is_some()) {
+                    println!("  {}", name);
+                }
+                return Ok(());
+            }
+            let template = match template.as_deref() {
+                None | Some("auto") => {
+                    let (template, reason) = pick_template();
+                    // with --stdout, stdout carries only the template
+                    if stdout {
+                        eprintln!("{} {}", tag(), reason);
+                    } else {
+                        log!(args.opts.quiet, "{} {}", tag(), reason);
+                    }
+                    template
+                }
+                Some(name) => template_for(name).unwrap_or(DEFAULT_TEMPLATE),
+            };
+            if stdout {
+                std::io::stdout().write_all(template)?;
+                return Ok(());
+            }
 
-            if Path::new("cue.toml").exists() {
-                log!(args.quiet, "{} cue.toml already exists", CUE.green());
+            if Path::new("cue.toml").exists() && !force {
+                log!(
+                    args.opts.quiet,
+                    "{} cue.toml already exists — use --force to overwrite it",
+                    tag()
+                );
             } else {
+                if Path::new("cue.toml").exists() {
+                    fs::copy("cue.toml", "cue.toml.bak")?;
+                    log!(
+                        args.opts.quiet,
+                        "{} old cue.toml backed up to cue.toml.bak",
+                        tag()
+                    );
+                }
                 let mut file = File::create("cue.toml")?;
                 file.write_all(template)?;
                 log!(
-                    args.quiet,
+                    args.opts.quiet,
                     "{} cue.toml created — edit it then run cue",
-                    CUE.green()
+                    tag()
                 );
             }
         }
@@ -1,7 +1,8 @@
 use serial_test::serial;
 use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
 fn cue() -> Command {
     let mut cmd = Command::new("cargo");
@@ -9,6 +10,43 @@ fn cue() -> Command {
     cmd
 }
 
+// a throwaway project with an empty `src` to watch, so tests that need a
+// config file never write one into the crate root
+struct Project(tempfile::TempDir);
+
+impl Project {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        fs::create_dir(dir.path().join("src")).expect("failed to create src");
+        Project(dir)
+    }
+
+    fn with_config(content: &str) -> Self {
+        let project = Self::new();
+        project.write("cue.toml", content);
+        project
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.0.path().join(name)
+    }
+
+    fn write(&self, name: &str, content: &str) {
+        fs::write(self.path(name), content).expect("failed to write");
+    }
+
+    fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.path(name)).unwrap_or_default()
+    }
+
+    // the binary itself, since cargo run can't find the manifest from here
+    fn cue(&self) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_cue"));
+        cmd.current_dir(self.0.path());
+        cmd
+    }
+}
+
 fn stderr(output: &std::process::Output) -> String {
     String::from_utf8_lossy(&output.stderr).to_string()
 }
@@ -47,6 +85,25 @@ fn test_path_does_not_exist() {
     assert!(stderr(&output).contains("doesn't exist"));
 }
 
+#[test]
+fn test_all_missing_paths_reported() {
+    let output = cue()
+        .args([
+            "-w",
+            "missing_path_a",
+            "src",
+            "missing_path_b",
+            "-r",
+            "echo hello",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("'missing_path_a' doesn't exist"));
+    assert!(stderr(&output).contains("'missing_path_b' doesn't exist"));
+}
+
 #[test]
 fn test_command_does_not_exist() {
     let output = cue()
@@ -85,7 +142,7 @@ fn test_task_add_and_list() {
     assert!(stdout(&list).contains("test_task_list"));
 
     cue()
-        .args(["task", "remove", "test_task_list"])
+        .args(["task", "remove", "test_task_list", "-y"])
         .output()
         .expect("failed to run");
 }
@@ -126,7 +183,7 @@ fn test_task_remove_existing() {
         .expect("failed to run");
 
     let remove = cue()
-        .args(["task", "remove", "test_task_remove"])
+        .args(["task", "remove", "test_task_remove", "-y"])
         .output()
         .expect("failed to run");
 
@@ -134,6 +191,35 @@ fn test_task_remove_existing() {
     assert!(stdout(&remove).contains("removed"));
 }
 
+#[test]
+fn test_task_remove_requires_yes_without_tty() {
+    cue()
+        .args([
+            "task",
+            "add",
+            "test_task_remove_confirm",
+            "-w",
+            "src",
+            "-r",
+            "echo hi",
+        ])
+        .output()
+        .expect("failed to run");
+
+    let refused = cue()
+        .args(["task", "remove", "test_task_remove_confirm"])
+        .output()
+        .expect("failed to run");
+    let removed = cue()
+        .args(["task", "remove", "test_task_remove_confirm", "--yes"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!refused.status.success());
+    assert!(stderr(&refused).contains("--yes"));
+    assert!(removed.status.success());
+}
+
 #[test]
 fn test_task_remove_not_found() {
     let output = cue()
@@ -169,7 +255,7 @@ fn test_task_edit_run() {
     assert!(stdout(&edit).contains("updated"));
 
     cue()
-        .args(["task", "remove", "test_task_edit"])
+        .args(["task", "remove", "test_task_edit", "-y"])
         .output()
         .expect("failed to run");
 }
@@ -234,55 +320,88 @@ fn test_run_global_flag() {
 }
 
 #[test]
-#[serial]
 fn test_init_creates_file() {
-    let existed = Path::new("cue.toml").exists();
-    if existed {
-        fs::rename("cue.toml", "cue.toml.bak").ok();
-    }
+    let project = Project::new();
 
-    let output = cue().args(["init"]).output().expect("failed to run");
+    let output = project
+        .cue()
+        .args(["init"])
+        .output()
+        .expect("failed to run");
 
     assert!(output.status.success());
-    assert!(Path::new("cue.toml").exists());
+    assert!(project.path("cue.toml").exists());
     assert!(stdout(&output).contains("created"));
-
-    fs::remove_file("cue.toml").ok();
-    if existed {
-        fs::rename("cue.toml.bak", "cue.toml").ok();
-    }
 }
 
 #[test]
-#[serial]
-
 fn test_init_already_exists() {
-    fs::write("cue.toml", "[tasks]").ok();
+    let project = Project::with_config("[tasks]");
 
-    let output = cue().args(["init"]).output().expect("failed to run");
+    let output = project
+        .cue()
+        .args(["init"])
+        .output()
+        .expect("failed to run");
 
     assert!(output.status.success());
     assert!(stdout(&output).contains("already exists"));
+    assert!(stdout(&output).contains("--force"));
+}
+
+#[test]
+fn test_init_stdout_does_not_write() {
+    let project = Project::with_config("[tasks]");
+
+    let output = project
+        .cue()
+        .args(["init", "rust", "--stdout"])
+        .output()
+        .expect("failed to run");
+    let content = project.read("cue.toml");
 
-    fs::remove_file("cue.toml").ok();
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("default = \"run\""));
+    assert_eq!(content, "[tasks]");
+}
+
+#[test]
+fn test_init_auto_detects_project() {
+    let output = cue()
+        .args(["init", "auto", "--stdout"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("cargo run"));
+    assert!(stderr(&output).contains("found Cargo.toml — using the rust template"));
+}
+
+#[test]
+fn test_init_force_overwrites() {
+    let project = Project::with_config("[tasks]");
+
+    let output = project
+        .cue()
+        .args(["init", "rust", "--force"])
+        .output()
+        .expect("failed to run");
+    let content = project.read("cue.toml");
+    let backup = project.read("cue.toml.bak");
+
+    assert!(output.status.success());
+    assert!(content.contains("cargo run"));
+    assert_eq!(backup, "[tasks]");
 }
 
 #[test]
-#[serial]
 fn test_no_args_no_toml_no_global_flag() {
-    let existed = Path::new("cue.toml").exists();
-    if existed {
-        fs::rename("cue.toml", "cue.toml.bak").ok();
-    }
+    let project = Project::new();
 
-    let output = cue().output().expect("failed to run");
+    let output = project.cue().output().expect("failed to run");
 
     assert!(!output.status.success());
     assert!(stderr(&output).contains("no 'cue.toml' found"));
-
-    if existed {
-        fs::rename("cue.toml.bak", "cue.toml").ok();
-    }
 }
 
 #[test]
@@ -317,7 +436,7 @@ fn test_task_rename() {
     assert!(!stdout(&list).contains("test_task_rename "));
 
     cue()
-        .args(["task", "remove", "test_task_renamed"])
+        .args(["task", "remove", "test_task_renamed", "-y"])
         .output()
         .expect("failed to run");
 }
@@ -332,3 +451,2568 @@ fn test_task_rename_not_found() {
     assert!(!output.status.success());
     assert!(stderr(&output).contains("not found"));
 }
+
+#[test]
+fn test_task_list_sorted() {
+    for name in ["test_task_sort_b", "test_task_sort_a"] {
+        cue()
+            .args(["task", "add", name, "-w", "src", "-r", "echo hi"])
+            .output()
+            .expect("failed to run");
+    }
+
+    let list = cue()
+        .args(["task", "list"])
+        .output()
+        .expect("failed to run");
+    let out = stdout(&list);
+
+    let a = out.find("test_task_sort_a").expect("missing task a");
+    let b = out.find("test_task_sort_b").expect("missing task b");
+    assert!(a < b);
+
+    for name in ["test_task_sort_a", "test_task_sort_b"] {
+        cue()
+            .args(["task", "remove", name, "-y"])
+            .output()
+            .expect("failed to run");
+    }
+}
+
+#[test]
+fn test_extensions_skip_ignored_dirs() {
+    let output = cue()
+        .args([
+            "-e",
+            "rs",
+            "-r",
+            "this_command_does_not_exist_xyz",
+            "--enumerate",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("main.rs"));
+    assert!(!stdout(&output).contains("target"));
+}
+
+#[test]
+fn test_extensions_exclusions_win() {
+    let output = cue()
+        .args([
+            "-e",
+            "rs",
+            "!main.rs",
+            "-r",
+            "echo hi",
+            "--enumerate",
+            "--dry-run",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("test.rs"));
+    assert!(!stdout(&output).contains("main.rs"));
+}
+
+#[test]
+fn test_extensions_exclusions_match_whole_suffix() {
+    let project = Project::new();
+    project.write("src/lexer.generated.rs", "");
+    project.write("src/regenerated.rs", "");
+
+    let output = project
+        .cue()
+        .args([
+            "-e",
+            "rs",
+            "!.generated.rs",
+            "-r",
+            "echo hi",
+            "--enumerate",
+            "--dry-run",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("regenerated.rs"));
+    assert!(!stdout(&output).contains("lexer.generated.rs"));
+}
+
+#[test]
+fn test_extensions_compound_suffix() {
+    let project = Project::new();
+    project.write("src/cue_compound_test.spec.ts", "");
+    project.write("src/cue_compound_test.ts", "");
+
+    let output = project
+        .cue()
+        .args(["-e", "spec.ts", "-r", "echo hi", "--enumerate", "--dry-run"])
+        .output()
+        .expect("failed to run");
+    let dotted = project
+        .cue()
+        .args([
+            "-e",
+            ".spec.ts",
+            "-r",
+            "echo hi",
+            "--enumerate",
+            "--dry-run",
+        ])
+        .output()
+        .expect("failed to run");
+    let excluded = project
+        .cue()
+        .args([
+            "-e",
+            ".ts",
+            "!.spec.ts",
+            "-r",
+            "echo hi",
+            "--enumerate",
+            "--dry-run",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("cue_compound_test.spec.ts"));
+    assert!(!stdout(&output).contains("cue_compound_test.ts\n"));
+    assert!(stdout(&dotted).contains("cue_compound_test.spec.ts"));
+    assert!(!stdout(&dotted).contains("cue_compound_test.ts\n"));
+    assert!(stdout(&excluded).contains("cue_compound_test.ts\n"));
+    assert!(!stdout(&excluded).contains("cue_compound_test.spec.ts"));
+}
+
+#[test]
+fn test_enumerate_cache_follows_cwd_and_changes() {
+    let first = Project::new();
+    let second = Project::new();
+    first.write("src/one.cuecache", "");
+    second.write("src/other.cuecache", "");
+    let enumerate = |project: &Project| {
+        let output = project
+            .cue()
+            .args([
+                "-e",
+                "cuecache",
+                "-r",
+                "echo hi",
+                "--enumerate",
+                "--dry-run",
+            ])
+            .output()
+            .expect("failed to run");
+        stdout(&output)
+    };
+
+    let before = enumerate(&first);
+    let elsewhere = enumerate(&second);
+    first.write("src/two.cuecache", "");
+    let after = enumerate(&first);
+
+    assert!(before.contains("one.cuecache"));
+    assert!(elsewhere.contains("other.cuecache"));
+    assert!(!elsewhere.contains("one.cuecache"));
+    assert!(after.contains("two.cuecache"));
+}
+
+#[test]
+fn test_extensions_respect_gitignore() {
+    let project = Project::new();
+    // .gitignore only counts inside a git repository
+    Command::new("git")
+        .args(["init", "-q"])
+        .current_dir(project.path(""))
+        .status()
+        .expect("failed to run git");
+    project.write(".gitignore", "*.so\n");
+    project.write("cue_gitignore_test.so", "");
+
+    let ignored = project
+        .cue()
+        .args([
+            "-e",
+            "so",
+            "-r",
+            "this_command_does_not_exist_xyz",
+            "--enumerate",
+        ])
+        .output()
+        .expect("failed to run");
+    let included = project
+        .cue()
+        .args([
+            "-e",
+            "so",
+            "-r",
+            "this_command_does_not_exist_xyz",
+            "--enumerate",
+            "--no-gitignore",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(!stdout(&ignored).contains("cue_gitignore_test.so"));
+    assert!(stdout(&included).contains("cue_gitignore_test.so"));
+}
+
+#[test]
+fn test_extensions_watch_top_level_dirs() {
+    let output = cue()
+        .args(["-e", "rs", "-r", "echo hi", "--dry-run"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("./src"));
+    assert!(!stdout(&output).contains("main.rs"));
+    assert!(!stdout(&output).contains("target"));
+}
+
+#[test]
+fn test_extensions_skip_nested_ignored_dirs() {
+    let project = Project::new();
+    fs::create_dir_all(project.path("src/nested/node_modules")).expect("failed to create dirs");
+
+    let output = project
+        .cue()
+        .args(["-e", "rs", "-r", "echo hi", "--dry-run"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("nested"));
+    assert!(!stdout(&output).contains("node_modules"));
+}
+
+#[test]
+fn test_extensions_max_depth() {
+    let shallow = cue()
+        .args(["-e", "rs", "-r", "echo hi", "--max-depth", "1", "--dry-run"])
+        .output()
+        .expect("failed to run");
+    let deeper = cue()
+        .args(["-e", "rs", "-r", "echo hi", "--max-depth", "2", "--dry-run"])
+        .output()
+        .expect("failed to run");
+
+    assert!(shallow.status.success());
+    assert!(!stdout(&shallow).contains("./src"));
+    assert!(deeper.status.success());
+    assert!(stdout(&deeper).contains("./src"));
+    assert!(!stdout(&deeper).contains("target"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinked_watch_path_shows_target() {
+    let project = Project::new();
+    std::os::unix::fs::symlink("src", project.path("cue_symlink_test")).ok();
+
+    let output = project
+        .cue()
+        .args(["-w", "cue_symlink_test", "-r", "echo hi", "--dry-run"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    let target = fs::canonicalize(project.path("src")).unwrap();
+    assert!(stdout(&output).contains(&format!("links to {}", target.display())));
+}
+
+#[test]
+fn test_extensions_filter_explicit_watch_paths() {
+    let output = cue()
+        .args(["-w", "src", "-e", "rs", "-r", "echo hi", "--dry-run"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("    src"));
+    assert!(!stdout(&output).contains("main.rs"));
+}
+
+#[test]
+fn test_watch_glob_expands() {
+    let output = cue()
+        .args(["-w", "src/*.rs", "-r", "this_command_does_not_exist_xyz"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("main.rs"));
+    assert!(stderr(&output).contains("not found"));
+}
+
+#[test]
+fn test_watch_glob_no_matches() {
+    let output = cue()
+        .args(["-w", "src/*.nothing_matches", "-r", "echo hello"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("doesn't match any files"));
+}
+
+#[test]
+fn test_once_success() {
+    let output = cue()
+        .args(["-w", "src", "-r", "echo once_output", "--once"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("once_output"));
+}
+
+#[test]
+fn test_once_propagates_exit_code() {
+    let output = cue()
+        .args(["-w", "src", "-r", "exit 3", "--shell", "--once"])
+        .output()
+        .expect("failed to run");
+
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_placeholders_empty_without_change() {
+    let output = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo [{file}{name}{dir}{ext}{files}]",
+            "--once",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("[]"));
+}
+
+#[test]
+fn test_placeholders_quoted_in_shell_mode() {
+    let dir = Path::new("target/cue_placeholder_quote_test");
+    fs::create_dir_all(dir).ok();
+    let mut child = cue()
+        .args([
+            "-w",
+            "target/cue_placeholder_quote_test",
+            "-r",
+            "echo saw {name}",
+            "--shell",
+            "--watch-only",
+            "--until",
+            "saw",
+            "--no-clear",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let mut out = String::new();
+    while !out.contains("ready") {
+        if reader.read_line(&mut out).unwrap_or(0) == 0 {
+            break;
+        }
+    }
+    fs::write(dir.join("a;echo INJECTED;#.txt"), "x").ok();
+    let status = child.wait().expect("failed to run");
+    reader.read_to_string(&mut out).ok();
+    fs::remove_dir_all(dir).ok();
+
+    assert!(status.success());
+    assert!(out.contains("saw a;echo INJECTED;#.txt"));
+    assert!(!out.contains("\nINJECTED"));
+}
+
+#[test]
+fn test_files_placeholder_quoted_in_shell_mode() {
+    let dir = Path::new("target/cue_files_quote_test");
+    fs::create_dir_all(dir).ok();
+    let mut child = cue()
+        .args([
+            "-w",
+            "target/cue_files_quote_test",
+            "-r",
+            "printf '<%s>\\n' {files}",
+            "--shell",
+            "--watch-only",
+            "--until",
+            "words",
+            "--no-clear",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let mut out = String::new();
+    while !out.contains("ready") {
+        if reader.read_line(&mut out).unwrap_or(0) == 0 {
+            break;
+        }
+    }
+    fs::write(dir.join("two words.txt"), "x").ok();
+    let status = child.wait().expect("failed to run");
+    reader.read_to_string(&mut out).ok();
+    fs::remove_dir_all(dir).ok();
+
+    assert!(status.success());
+    assert!(out.contains(" words.txt>"));
+    assert!(!out.contains("<words.txt>"));
+}
+
+#[test]
+fn test_run_sequence_in_order() {
+    let output = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo step_one",
+            "-r",
+            "echo step_two",
+            "--once",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.find("step_one").unwrap() < out.find("step_two").unwrap());
+}
+
+#[test]
+fn test_run_flag_takes_one_value() {
+    let project =
+        Project::with_config("[tasks.build]\nwatch = [\"src\"]\nrun = \"echo from task\"\n");
+
+    let output = project
+        .cue()
+        .args(["run", "-r", "make", "build", "--dry-run", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("run: make\n"));
+    assert!(stdout(&output).contains("    src"));
+}
+
+#[test]
+fn test_run_sequence_stops_on_failure() {
+    let output = cue()
+        .args(["-w", "src", "-r", "false", "-r", "echo step_two", "--once"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(!stdout(&output).contains("step_two"));
+}
+
+#[test]
+fn test_run_sequence_keep_going() {
+    let output = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "false",
+            "-r",
+            "echo step_two",
+            "--once",
+            "--keep-going",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("step_two"));
+}
+
+#[test]
+#[serial]
+fn test_hooks_before_failure_skips_run() {
+    let project = Project::with_config(
+        "[tasks.hooks]\nwatch = [\"src\"]\nbefore = \"false\"\nrun = \"echo main_ran\"\nafter = \"echo after_ran\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "hooks", "--once"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(!stdout(&output).contains("main_ran"));
+    assert!(stdout(&output).contains("after_ran"));
+}
+
+#[test]
+fn test_env_file_injects_variables() {
+    let project = Project::new();
+    project.write("cue_test_inject.env", "CUE_TEST_VAR=from_env_file\n");
+
+    let output = project
+        .cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo $CUE_TEST_VAR",
+            "--shell",
+            "--once",
+            "--env-file",
+            "cue_test_inject.env",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("from_env_file"));
+}
+
+#[test]
+fn test_env_file_missing() {
+    let output = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo hello",
+            "--once",
+            "--env-file",
+            "this_env_file_does_not_exist.env",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("failed to read env file"));
+}
+
+#[test]
+fn test_validate_reports_all_problems() {
+    let project = Project::with_config(
+        "default = \"missing\"\n[tasks.ok]\nwatch = [\"src\"]\nrun = \"echo hi\"\n[tasks.bad]\nwatch = [\"no_such_dir_xyz\"]\nrun = \"this_command_does_not_exist_xyz\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["validate", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("default task 'missing' doesn't exist"));
+    assert!(out.contains("'no_such_dir_xyz' doesn't exist"));
+    assert!(out.contains("command 'this_command_does_not_exist_xyz' not found"));
+    assert!(stderr(&output).contains("3 problems found"));
+}
+
+#[test]
+fn test_validate_valid_config() {
+    let project = Project::with_config(
+        "default = \"ok\"\n[tasks.ok]\nwatch = [\"src\"]\nrun = \"echo hi\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["validate", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("all tasks are valid"));
+}
+
+#[test]
+fn test_validate_unknown_color() {
+    let project = Project::with_config(
+        "[tasks.ok]\nwatch = [\"src\"]\nrun = \"echo hi\"\ncolor = \"cyan\"\n[tasks.bad]\nwatch = [\"src\"]\nrun = \"echo hi\"\ncolor = \"rainbow\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["validate", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("unknown color 'rainbow'"));
+    assert!(stderr(&output).contains("1 problem found"));
+}
+
+#[test]
+#[serial]
+fn test_config_found_in_parent_dir() {
+    let project = Project::with_config(
+        "default = \"here\"\n[tasks.here]\nwatch = [\"src\"]\nrun = \"pwd\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "--once"])
+        .current_dir(project.path("src"))
+        .output()
+        .expect("failed to run");
+    let invoked = project
+        .cue()
+        .args(["run", "--once", "--cwd-invocation"])
+        .current_dir(project.path("src"))
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(!stdout(&output).trim_end().ends_with("src"));
+    assert!(invoked.status.success());
+    assert!(stdout(&invoked).trim_end().ends_with("src"));
+}
+
+#[test]
+fn test_config_flag_missing_file() {
+    let output = cue()
+        .args(["run", "--config", "does-not-exist.toml"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("config file 'does-not-exist.toml' doesn't exist"));
+}
+
+#[test]
+#[serial]
+fn test_local_config_merges_global_tasks() {
+    cue()
+        .args([
+            "task",
+            "add",
+            "test_merge_global",
+            "-w",
+            "src",
+            "-r",
+            "echo from global",
+        ])
+        .output()
+        .expect("failed to run");
+    let project =
+        Project::with_config("[tasks.local]\nwatch = [\"src\"]\nrun = \"echo from local\"\n");
+
+    let merged = project
+        .cue()
+        .args(["run", "test_merge_global", "--once"])
+        .output()
+        .expect("failed to run");
+    let separate = project
+        .cue()
+        .args(["run", "test_merge_global", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_merge_global", "-y"])
+        .output()
+        .expect("failed to run");
+
+    assert!(merged.status.success());
+    assert!(stdout(&merged).contains("from global"));
+    assert!(!separate.status.success());
+}
+
+// a throwaway global config, so the test's [defaults] can't touch the real one
+#[cfg(target_os = "linux")]
+#[test]
+fn test_global_defaults_stay_with_global_tasks() {
+    let project = Project::with_config(
+        "[tasks.local]\nwatch = [\"src\"]\nrun = \"sh -c 'echo scope=$CUE_GLOBAL'\"\n",
+    );
+    fs::create_dir_all(project.path("xdg/cue")).expect("failed to create dir");
+    project.write(
+        "xdg/cue/default-config.toml",
+        "[defaults]\nenv = { CUE_GLOBAL = \"global\" }\n\n[tasks.global]\nwatch = [\"src\"]\nrun = \"sh -c 'echo scope=$CUE_GLOBAL'\"\n",
+    );
+
+    let run = |task: &str| {
+        project
+            .cue()
+            .env("XDG_CONFIG_HOME", project.path("xdg"))
+            .args(["run", task, "--once"])
+            .output()
+            .expect("failed to run")
+    };
+    let local = run("local");
+    let global = run("global");
+
+    assert!(local.status.success());
+    assert!(stdout(&local).contains("scope="));
+    assert!(!stdout(&local).contains("scope=global"));
+    assert!(global.status.success());
+    assert!(stdout(&global).contains("scope=global"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_global_default_ignored_in_a_project() {
+    let project =
+        Project::with_config("[tasks.build]\nwatch = [\"src\"]\nrun = \"echo from local\"\n");
+    fs::create_dir_all(project.path("xdg/cue")).expect("failed to create dir");
+    project.write(
+        "xdg/cue/default-config.toml",
+        "default = \"test\"\n\n[tasks.test]\nwatch = [\"src\"]\nrun = \"echo from global\"\n",
+    );
+
+    let output = project
+        .cue()
+        .env("XDG_CONFIG_HOME", project.path("xdg"))
+        .arg("--once")
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(!stdout(&output).contains("from global"));
+    assert!(stderr(&output).contains("no default task"));
+}
+
+#[test]
+fn test_task_show() {
+    cue()
+        .args([
+            "task",
+            "add",
+            "test_task_show",
+            "-w",
+            "src",
+            "tests",
+            "-r",
+            "echo hi",
+        ])
+        .output()
+        .expect("failed to run");
+
+    let output = cue()
+        .args(["task", "show", "test_task_show"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_task_show", "-y"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("    src\n    tests\n"));
+    assert!(stdout(&output).contains("run: echo hi"));
+}
+
+#[test]
+fn test_task_description_listed() {
+    cue()
+        .args([
+            "task",
+            "add",
+            "test_task_description",
+            "-w",
+            "src",
+            "-r",
+            "echo hi",
+            "--description",
+            "say hello",
+        ])
+        .output()
+        .expect("failed to run");
+
+    let list = cue()
+        .args(["task", "list"])
+        .output()
+        .expect("failed to run");
+    let show = cue()
+        .args(["task", "show", "test_task_description"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_task_description", "-y"])
+        .output()
+        .expect("failed to run");
+
+    assert!(stdout(&list).contains("test_task_description — say hello"));
+    assert!(stdout(&show).contains("  say hello\n"));
+}
+
+#[test]
+fn test_task_show_not_found() {
+    let output = cue()
+        .args(["task", "show", "task_that_does_not_exist_xyz"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("not found"));
+}
+
+#[test]
+fn test_task_copy() {
+    cue()
+        .args([
+            "task",
+            "add",
+            "test_task_copy",
+            "-w",
+            "src",
+            "-r",
+            "echo copied",
+        ])
+        .output()
+        .expect("failed to run");
+
+    let copy = cue()
+        .args(["task", "copy", "test_task_copy", "test_task_copied"])
+        .output()
+        .expect("failed to run");
+    let again = cue()
+        .args(["task", "copy", "test_task_copy", "test_task_copied"])
+        .output()
+        .expect("failed to run");
+    let show = cue()
+        .args(["task", "show", "test_task_copied"])
+        .output()
+        .expect("failed to run");
+
+    for name in ["test_task_copy", "test_task_copied"] {
+        cue()
+            .args(["task", "remove", name, "-y"])
+            .output()
+            .expect("failed to run");
+    }
+
+    assert!(copy.status.success());
+    assert!(!again.status.success());
+    assert!(stderr(&again).contains("already exists"));
+    assert!(stdout(&show).contains("run: echo copied"));
+}
+
+#[test]
+fn test_task_list_json() {
+    cue()
+        .args([
+            "task",
+            "add",
+            "test_task_list_json",
+            "-w",
+            "src",
+            "-r",
+            "echo hi",
+        ])
+        .output()
+        .expect("failed to run");
+
+    let output = cue()
+        .args(["task", "list", "--json"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_task_list_json", "-y"])
+        .output()
+        .expect("failed to run");
+
+    let json: serde_json::Value =
+        serde_json::from_str(&stdout(&output)).expect("output is not valid JSON");
+    assert!(json.get("default").is_some());
+    assert_eq!(json["tasks"]["test_task_list_json"]["run"], "echo hi");
+}
+
+#[test]
+fn test_task_list_names_only() {
+    cue()
+        .args([
+            "task",
+            "add",
+            "test_task_list_names_only",
+            "-w",
+            "src",
+            "-r",
+            "echo hi",
+        ])
+        .output()
+        .expect("failed to run");
+
+    let output = cue()
+        .args(["task", "list", "--names-only"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_task_list_names_only", "-y"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.lines().any(|l| l == "test_task_list_names_only"));
+    assert!(!out.contains("[cue]"));
+    assert!(!out.contains("echo hi"));
+}
+
+#[test]
+fn test_track_shows_last_run_in_list() {
+    cue()
+        .args([
+            "task",
+            "add",
+            "test_track_last_run",
+            "-w",
+            "src",
+            "-r",
+            "false",
+        ])
+        .output()
+        .expect("failed to run");
+
+    let run = cue()
+        .args(["run", "test_track_last_run", "-g", "--once", "--track"])
+        .output()
+        .expect("failed to run");
+    let list = cue()
+        .args(["task", "list"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_track_last_run", "-y"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!run.status.success());
+    let line = stdout(&list)
+        .lines()
+        .find(|l| l.contains("test_track_last_run"))
+        .map(str::to_string)
+        .unwrap_or_default();
+    assert!(line.contains("last: failed"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_track_keeps_local_results_off_global_tasks() {
+    let project = Project::with_config("[tasks.test]\nwatch = [\"src\"]\nrun = \"false\"\n");
+    fs::create_dir_all(project.path("xdg/cue")).expect("failed to create dir");
+    project.write(
+        "xdg/cue/default-config.toml",
+        "[tasks.test]\nwatch = [\"src\"]\nrun = \"true\"\n",
+    );
+    let cue = || {
+        let mut cue = project.cue();
+        cue.env("XDG_CONFIG_HOME", project.path("xdg"));
+        cue
+    };
+
+    let run = cue()
+        .args(["run", "test", "--once", "--track"])
+        .output()
+        .expect("failed to run");
+    let list = cue()
+        .args(["task", "list"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!run.status.success());
+    assert!(stdout(&list).contains("test"));
+    assert!(!stdout(&list).contains("last:"));
+}
+
+#[test]
+fn test_completions_bash() {
+    let output = cue()
+        .args(["completions", "bash"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("_cue()"));
+}
+
+#[test]
+fn test_init_list() {
+    let output = cue()
+        .args(["init", "--list"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("  rust\n"));
+    assert!(stdout(&output).contains("  python\n"));
+    assert!(stdout(&output).contains("  shell\n"));
+}
+
+#[test]
+fn test_color_always_and_never() {
+    let always = cue()
+        .args(["init", "--list", "--color", "always"])
+        .output()
+        .expect("failed to run");
+    let never = cue()
+        .args(["init", "--list", "--color", "never"])
+        .env("CLICOLOR_FORCE", "1")
+        .output()
+        .expect("failed to run");
+
+    assert!(stdout(&always).contains("\x1b["));
+    assert!(!stdout(&never).contains("\x1b["));
+}
+
+#[test]
+fn test_wait_allows_missing_path() {
+    let output = cue()
+        .args([
+            "-w",
+            "dir_that_does_not_exist_xyz",
+            "-r",
+            "echo hi",
+            "--wait",
+            "--once",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("waiting for it"));
+}
+
+#[test]
+fn test_invalid_time_format() {
+    let output = cue()
+        .args(["-w", "src", "-r", "echo hi", "--time-format", "%Q"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("invalid time format '%Q'"));
+}
+
+#[test]
+fn test_durations_accept_units() {
+    let output = cue()
+        .args(["-w", "src", "-r", "sleep 5", "--once", "--timeout", "0.2s"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("timed out after 200ms"));
+}
+
+#[test]
+fn test_invalid_duration() {
+    let output = cue()
+        .args(["-w", "src", "-r", "echo hi", "--debounce", "5x"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("unknown unit in '5x'"));
+}
+
+#[test]
+fn test_invalid_separator_width() {
+    let output = cue()
+        .args(["-w", "src", "-r", "echo hi", "--separator-width", "wide"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("expected 'full', 'half' or a number of columns"));
+}
+
+#[test]
+fn test_prefix_tags_output() {
+    let output = cue()
+        .args(["-w", "src", "-r", "echo hello", "--once", "--prefix"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("[run] hello"));
+}
+
+#[test]
+fn test_label_replaces_cue_prefix() {
+    let output = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo hello",
+            "--once",
+            "--prefix",
+            "--label",
+            "api",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("[cue:api] checking paths"));
+    assert!(stdout(&output).contains("[api] hello"));
+}
+
+#[test]
+fn test_timeout_kills_command() {
+    let output = cue()
+        .args(["-w", "src", "-r", "sleep 5", "--once", "--timeout", "200"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("timed out after 200ms"));
+}
+
+#[test]
+fn test_until_stops_watching_on_match() {
+    let output = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "sh -c 'echo Finished build; sleep 30'",
+            "--until",
+            "Finished",
+            "--no-clear",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Finished build"));
+    assert!(stdout(&output).contains("output matched 'Finished' — stopping"));
+}
+
+#[test]
+fn test_until_rejects_invalid_pattern() {
+    let output = cue()
+        .args(["-w", "src", "-r", "echo hi", "--once", "--until", "("])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("invalid --until pattern"));
+}
+
+#[test]
+#[serial]
+fn test_yaml_and_json_configs() {
+    let project = Project::new();
+    project.write(
+        "cue.yaml",
+        "default: hi\ntasks:\n  hi:\n    watch: [src]\n    run: echo from yaml\n",
+    );
+    let yaml = project
+        .cue()
+        .args(["run", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    let project = Project::new();
+    project.write(
+        "cue.json",
+        r#"{"default": "hi", "tasks": {"hi": {"watch": ["src"], "run": "echo from json"}}}"#,
+    );
+    let json = project
+        .cue()
+        .args(["run", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(stdout(&yaml).contains("from yaml"));
+    assert!(stdout(&json).contains("from json"));
+}
+
+#[test]
+fn test_task_quiet_from_config() {
+    let project = Project::with_config(
+        "default = \"hush\"\n[tasks.hush]\nwatch = [\"src\"]\nrun = \"echo hi\"\nquiet = true\ndebounce = 500\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(!stdout(&output).contains("checking paths"));
+    assert!(stdout(&output).contains("hi"));
+}
+
+#[test]
+fn test_task_deps_run_first() {
+    let project = Project::with_config(
+        "default = \"main\"\n\
+         [tasks.gen]\nwatch = [\"src\"]\nrun = \"echo gen-ran\"\n\
+         [tasks.main]\nwatch = [\"src\"]\nrun = \"echo main-ran\"\ndeps = [\"gen\"]\n\
+         [tasks.a]\nwatch = [\"src\"]\nrun = \"echo a\"\ndeps = [\"b\"]\n\
+         [tasks.b]\nwatch = [\"src\"]\nrun = \"echo b\"\ndeps = [\"a\"]\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+    let cycle = project
+        .cue()
+        .args(["run", "a", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    let out = stdout(&output);
+    assert!(output.status.success());
+    assert!(out.find("gen-ran").unwrap() < out.find("main-ran").unwrap());
+    assert!(!cycle.status.success());
+    assert!(stderr(&cycle).contains("dependency cycle: a -> b -> a"));
+}
+
+#[test]
+fn test_watch_paths_from_stdin() {
+    let mut child = cue()
+        .args(["-w", "-", "-r", "echo hi", "--dry-run"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"src/main.rs\nCargo.toml\nsrc/main.rs\n")
+        .unwrap();
+    let output = child.wait_with_output().expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("    Cargo.toml"));
+    assert_eq!(stdout(&output).matches("    src/main.rs").count(), 1);
+}
+
+#[test]
+fn test_daemon_needs_log_file() {
+    let output = cue()
+        .args(["-w", "src", "-r", "echo hi", "--daemon"])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--daemon needs --log-file"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_daemon_gets_its_own_session() {
+    let project = Project::new();
+    project.write("src/lib.rs", "");
+
+    let output = project
+        .cue()
+        .args(["-w", "src", "-r", "echo hi", "--daemon"])
+        .args(["--log-file", "cue.log", "--pid-file", "cue.pid"])
+        .output()
+        .expect("failed to run");
+    let mut pid = String::new();
+    while pid.trim().is_empty() {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        pid = project.read("cue.pid");
+    }
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+    // session id and controlling terminal come after the `)` closing the
+    // command name: state ppid pgrp session tty_nr
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .map(|(_, rest)| rest.split_whitespace().collect())
+        .unwrap_or_default();
+    project
+        .cue()
+        .args(["stop", "--pid-file", "cue.pid"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert_eq!(fields.get(3), Some(&pid.trim()));
+    assert_eq!(fields.get(4), Some(&"0"));
+}
+
+#[test]
+fn test_dry_run_prints_plan() {
+    let output = cue()
+        .args(["-w", "src", "-r", "touch dry_run_marker", "--dry-run"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("run: touch dry_run_marker"));
+    assert!(!Path::new("dry_run_marker").exists());
+}
+
+#[test]
+fn test_verbose_logs_config_and_paths() {
+    let project = Project::with_config(
+        "default = \"hi\"\n[tasks.hi]\nwatch = [\"src\"]\nrun = \"echo hi\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "--once", "--no-merge", "-v"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("config file"));
+}
+
+#[test]
+fn test_log_file_strips_colors() {
+    let log = std::env::temp_dir().join("cue_test_log_file.log");
+    fs::remove_file(&log).ok();
+
+    let output = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo logged",
+            "--once",
+            "--prefix",
+            "--color",
+            "always",
+        ])
+        .arg("--log-file")
+        .arg(&log)
+        .output()
+        .expect("failed to run");
+
+    let content = fs::read_to_string(&log).unwrap_or_default();
+    fs::remove_file(&log).ok();
+
+    assert!(output.status.success());
+    assert!(content.contains("[cue] checking paths"));
+    assert!(content.contains("[run] logged"));
+    assert!(!content.contains('\x1b'));
+}
+
+#[test]
+fn test_events_rejects_unknown_kind() {
+    let output = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo hi",
+            "--once",
+            "--events",
+            "modify,bogus",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("possible values"));
+}
+
+#[test]
+fn test_run_several_tasks() {
+    let project = Project::with_config(
+        "[tasks.one]\nwatch = [\"src\"]\nrun = \"echo first\"\n\
+         [tasks.two]\nwatch = [\"src\"]\nrun = \"echo second\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "one", "two", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    let out = stdout(&output);
+    assert!(output.status.success());
+    assert!(out.contains("[one] first"));
+    assert!(out.contains("[two] second"));
+}
+
+#[test]
+fn test_dry_run_prints_every_task_plan() {
+    let project = Project::with_config(
+        "[tasks.one]\nwatch = [\"src\"]\nrun = \"echo first\"\n\
+         [tasks.two]\nwatch = [\"src\"]\nrun = \"echo second\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "one", "two", "--dry-run", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    let out = stdout(&output);
+    assert!(output.status.success());
+    assert!(out.contains("run: echo first"));
+    assert!(out.contains("run: echo second"));
+}
+
+#[test]
+fn test_default_list_runs_every_task() {
+    let project = Project::with_config(
+        "default = [\"one\", \"two\"]\n\
+         [tasks.one]\nwatch = [\"src\"]\nrun = \"echo first\"\n\
+         [tasks.two]\nwatch = [\"src\"]\nrun = \"echo second\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    let out = stdout(&output);
+    assert!(output.status.success());
+    assert!(out.contains("[one] first"));
+    assert!(out.contains("[two] second"));
+}
+
+#[test]
+fn test_bell_rings_once_per_failed_run_on_stderr() {
+    let project = Project::new();
+    let mut child = project
+        .cue()
+        .args(["-w", "src", "-r", "false", "-r", "false", "--keep-going"])
+        .args(["--bell", "--json-events"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut lines = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .map_while(Result::ok);
+    let out: Vec<String> = lines
+        .by_ref()
+        .take_while(|line| !line.contains("run_end"))
+        .collect();
+    child.kill().ok();
+    let output = child.wait_with_output().expect("failed to wait");
+
+    assert!(out.iter().all(|line| !line.contains('\x07')));
+    assert_eq!(stderr(&output).matches('\x07').count(), 1);
+}
+
+#[test]
+fn test_picker_needs_a_terminal() {
+    let project = Project::with_config(
+        "[tasks.one]\nwatch = [\"src\"]\nrun = \"echo first\"\n\
+         [tasks.two]\nwatch = [\"src\"]\nrun = \"echo second\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["--once", "--no-merge"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("no default task"));
+}
+
+#[test]
+fn test_json_events_stream() {
+    let output = cue()
+        .args(["-w", "src", "-r", "echo hi", "--once", "--json-events"])
+        .output()
+        .expect("failed to run");
+
+    let out = stdout(&output);
+    let events: Vec<serde_json::Value> = out
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("not a JSON line"))
+        .collect();
+
+    assert!(output.status.success());
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["event"], "run_start");
+    assert_eq!(events[1]["event"], "run_end");
+    assert_eq!(events[1]["code"], 0);
+    assert!(stderr(&output).contains("hi"));
+}
+
+#[test]
+fn test_json_events_keeps_other_output_off_stdout() {
+    let verbose = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo done",
+            "--until",
+            "done",
+            "--json-events",
+            "-v",
+        ])
+        .output()
+        .expect("failed to run");
+    let dry = cue()
+        .args(["-w", "src", "-r", "echo hi", "--dry-run", "--json-events"])
+        .output()
+        .expect("failed to run");
+
+    assert!(verbose.status.success());
+    for line in stdout(&verbose).lines() {
+        serde_json::from_str::<serde_json::Value>(line).expect("not a JSON line");
+    }
+    assert!(stderr(&verbose).contains("watching"));
+    assert!(dry.status.success());
+    assert!(stdout(&dry).is_empty());
+    assert!(stderr(&dry).contains("run: echo hi"));
+}
+
+#[test]
+fn test_stop_with_stale_pid_file() {
+    let pid_file = std::env::temp_dir().join("cue_test_stale.pid");
+    fs::write(&pid_file, "4194303").ok();
+
+    let output = cue()
+        .args(["stop", "--pid-file"])
+        .arg(&pid_file)
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("isn't running"));
+    assert!(!pid_file.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stop_leaves_reused_pid_alone() {
+    let project = Project::new();
+    let mut other = Command::new("sleep")
+        .arg("30")
+        .spawn()
+        .expect("failed to run sleep");
+    project.write("cue.pid", &other.id().to_string());
+
+    let output = project
+        .cue()
+        .args(["stop", "--pid-file", "cue.pid"])
+        .output()
+        .expect("failed to run");
+    let still_running = other.try_wait().ok().flatten().is_none();
+    other.kill().ok();
+    other.wait().ok();
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("isn't running"));
+    assert!(still_running);
+    assert!(!project.path("cue.pid").exists());
+}
+
+#[test]
+fn test_pid_file_not_left_behind_on_error() {
+    let pid_file = std::env::temp_dir().join("cue_test_error.pid");
+    fs::remove_file(&pid_file).ok();
+
+    let output = cue()
+        .args(["run", "cue_test_no_such_task", "-g", "--pid-file"])
+        .arg(&pid_file)
+        .output()
+        .expect("failed to run");
+
+    let late = cue()
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo hi",
+            "--time-format",
+            "%Q",
+            "--pid-file",
+        ])
+        .arg(&pid_file)
+        .output()
+        .expect("failed to run");
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("not found"));
+    assert!(!late.status.success());
+    assert!(stderr(&late).contains("invalid time format"));
+    assert!(!pid_file.exists());
+}
+
+#[test]
+fn test_relative_pid_file_beside_config_in_parent() {
+    let project = Project::with_config("[tasks.serve]\nwatch = [\".\"]\nrun = \"echo started\"\n");
+    let sub = project.path("src");
+    let mut child = project
+        .cue()
+        .args(["run", "serve", "--no-merge", "--pid-file", "cue.pid"])
+        .current_dir(&sub)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let started = lines
+        .by_ref()
+        .map_while(Result::ok)
+        .any(|line| line == "started");
+    let written = sub.join("cue.pid").exists();
+    let stop = project
+        .cue()
+        .args(["stop", "--pid-file", "cue.pid"])
+        .current_dir(&sub)
+        .output()
+        .expect("failed to run");
+    if !stop.status.success() {
+        child.kill().ok();
+    }
+    child.wait().expect("failed to wait");
+    let removed = !sub.join("cue.pid").exists();
+
+    assert!(started);
+    assert!(written);
+    assert!(stop.status.success());
+    assert!(removed);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stop_during_once_stops_the_command() {
+    let pid_file = std::env::temp_dir().join("cue_test_once.pid");
+    fs::remove_file(&pid_file).ok();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cue"))
+        .args(["-w", "src", "-r", "sleep 31.37", "--once", "--pid-file"])
+        .arg(&pid_file)
+        .stdin(Stdio::null())
+        .spawn()
+        .expect("failed to run");
+    while !pid_file.exists() && child.try_wait().ok().flatten().is_none() {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let stop = cue()
+        .args(["stop", "--pid-file"])
+        .arg(&pid_file)
+        .output()
+        .expect("failed to run");
+    let status = child.wait().expect("failed to wait");
+    let strays = Command::new("pgrep")
+        .args(["-f", "sleep 31.37"])
+        .output()
+        .expect("failed to run pgrep");
+
+    assert!(stop.status.success());
+    assert_eq!(status.code(), Some(130));
+    assert!(stdout(&strays).trim().is_empty());
+    assert!(!pid_file.exists());
+}
+
+#[test]
+fn test_watch_shallow_paths() {
+    let project = Project::with_config(
+        "default = \"top\"\n[tasks.top]\nwatch_shallow = [\"src\"]\nrun = \"echo hi\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "--dry-run", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("    src"));
+}
+
+#[test]
+fn test_watch_path_expands_env_vars() {
+    let output = cue()
+        .args(["-w", "$CUE_TEST_DIR", "-r", "echo hi", "--dry-run"])
+        .env("CUE_TEST_DIR", "src")
+        .output()
+        .expect("failed to run");
+    let unset = cue()
+        .args(["-w", "$CUE_TEST_UNSET_DIR", "-r", "echo hi", "--dry-run"])
+        .env_remove("CUE_TEST_UNSET_DIR")
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("src exists"));
+    assert!(!unset.status.success());
+    assert!(stderr(&unset).contains("CUE_TEST_UNSET_DIR isn't set"));
+}
+
+#[test]
+fn test_task_add_warns_about_missing_command() {
+    let loose = cue()
+        .args([
+            "task",
+            "add",
+            "test_task_add_check",
+            "-w",
+            "src",
+            "-r",
+            "definitely_not_a_real_binary_xyz",
+        ])
+        .output()
+        .expect("failed to run");
+    let strict = cue()
+        .args([
+            "task",
+            "add",
+            "test_task_add_check_strict",
+            "-w",
+            "src",
+            "-r",
+            "definitely_not_a_real_binary_xyz",
+            "--strict",
+        ])
+        .output()
+        .expect("failed to run");
+    let shown = cue()
+        .args(["task", "show", "test_task_add_check_strict"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_task_add_check", "-y"])
+        .output()
+        .expect("failed to run");
+
+    assert!(loose.status.success());
+    assert!(stderr(&loose).contains("Warning:"));
+    assert!(!strict.status.success());
+    assert!(!shown.status.success());
+}
+
+#[test]
+fn test_working_dir_flag() {
+    let output = cue()
+        .args(["-C", "src", "-w", "main.rs", "-r", "echo hi", "--dry-run"])
+        .output()
+        .expect("failed to run");
+    let missing = cue()
+        .args(["-C", "no_such_dir_xyz", "-w", "src", "-r", "echo hi"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("main.rs exists"));
+    assert!(!missing.status.success());
+    assert!(stderr(&missing).contains("no_such_dir_xyz"));
+}
+
+#[test]
+fn test_task_import_and_export() {
+    let file = std::env::temp_dir().join("cue_test_import.toml");
+    fs::write(
+        &file,
+        "[tasks.test_task_imported]\nwatch = [\"src\"]\nrun = \"echo imported\"\n",
+    )
+    .ok();
+
+    let first = cue()
+        .args(["task", "import"])
+        .arg(&file)
+        .output()
+        .expect("failed to run");
+    let again = cue()
+        .args(["task", "import"])
+        .arg(&file)
+        .output()
+        .expect("failed to run");
+    let export = cue()
+        .args(["task", "export"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_task_imported", "-y"])
+        .output()
+        .expect("failed to run");
+    fs::remove_file(&file).ok();
+
+    assert!(stdout(&first).contains("1 task imported"));
+    assert!(stdout(&again).contains("skipped 'test_task_imported'"));
+    assert!(stdout(&export).contains("[tasks.test_task_imported]"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_task_import_keeps_defaults() {
+    let project = Project::new();
+    project.write(
+        "shared.toml",
+        "[defaults]\nquiet = true\n\n[tasks.imported]\nwatch = [\"src\"]\nrun = \"echo imported\"\n",
+    );
+
+    let output = project
+        .cue()
+        .env("XDG_CONFIG_HOME", project.path("xdg"))
+        .args(["task", "import", "shared.toml"])
+        .output()
+        .expect("failed to run");
+    let global = project.read("xdg/cue/default-config.toml");
+
+    assert!(output.status.success());
+    assert!(global.contains("[tasks.imported]"));
+    assert!(global.contains("quiet = true"));
+}
+
+#[test]
+fn test_task_list_shows_cmd_and_args() {
+    let file = std::env::temp_dir().join("cue_test_list_cmd.toml");
+    fs::write(
+        &file,
+        "[tasks.test_task_list_cmd]\nwatch = [\"src\"]\ncmd = \"echo\"\nargs = [\"a  b\"]\n",
+    )
+    .ok();
+
+    cue()
+        .args(["task", "import"])
+        .arg(&file)
+        .output()
+        .expect("failed to run");
+    let output = cue()
+        .args(["task", "list"])
+        .output()
+        .expect("failed to run");
+
+    cue()
+        .args(["task", "remove", "test_task_list_cmd", "-y"])
+        .output()
+        .expect("failed to run");
+    fs::remove_file(&file).ok();
+
+    let line = stdout(&output)
+        .lines()
+        .find(|line| line.contains("test_task_list_cmd"))
+        .map(str::to_string)
+        .unwrap_or_default();
+    assert!(line.contains("run: \"echo 'a  b'\""));
+}
+
+#[test]
+fn test_where_prints_config_path() {
+    let project = Project::with_config("not valid toml [[[");
+
+    let local = project
+        .cue()
+        .args(["where"])
+        .output()
+        .expect("failed to run");
+    let global = project
+        .cue()
+        .args(["where", "-g"])
+        .output()
+        .expect("failed to run");
+
+    assert!(local.status.success());
+    assert!(stdout(&local).trim().ends_with("cue.toml"));
+    assert_ne!(stdout(&global), stdout(&local));
+}
+
+#[test]
+fn test_task_cmd_and_args() {
+    let project = Project::with_config(
+        "default = \"exact\"\n[tasks.exact]\nwatch = [\"src\"]\ncmd = \"echo\"\nargs = [\"it's\", \"a  b\"]\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("it's a  b"));
+}
+
+#[test]
+fn test_run_passthrough_args() {
+    let project = Project::with_config(
+        "[tasks.steps]\nwatch = [\"src\"]\nrun = [\"echo first\", \"echo last\"]\n",
+    );
+
+    let output = project
+        .cue()
+        .args([
+            "run",
+            "steps",
+            "--once",
+            "--no-merge",
+            "--",
+            "--flag",
+            "a b",
+        ])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("last --flag a b"));
+    assert!(!out.contains("first --flag"));
+}
+
+#[test]
+fn test_task_name_in_env() {
+    let project = Project::with_config(
+        "[tasks.named]\nwatch = [\"src\"]\nrun = \"sh -c 'echo task=$CUE_TASK path=$CUE_CHANGED_PATH'\"\n",
+    );
+
+    let output = project
+        .cue()
+        .args(["run", "named", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("task=named path=\n"));
+}
+
+#[test]
+fn test_defaults_apply_to_tasks() {
+    let project = Project::with_config(
+        "[defaults]\nquiet = true\nenv = { CUE_A = \"default\", CUE_B = \"default\" }\n\n[tasks.plain]\nwatch = [\"src\"]\nrun = \"sh -c 'echo $CUE_A $CUE_B'\"\n\n[tasks.own]\nwatch = [\"src\"]\nrun = \"sh -c 'echo $CUE_A $CUE_B'\"\nquiet = false\nenv = { CUE_B = \"task\" }\n",
+    );
+
+    let plain = project
+        .cue()
+        .args(["run", "plain", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+    let own = project
+        .cue()
+        .args(["run", "own", "--once", "--no-merge"])
+        .output()
+        .expect("failed to run");
+
+    assert!(plain.status.success());
+    assert!(stdout(&plain).contains("default default"));
+    assert!(!stdout(&plain).contains("checking paths"));
+    assert!(own.status.success());
+    assert!(stdout(&own).contains("default task"));
+    assert!(stdout(&own).contains("checking paths"));
+}
+
+#[test]
+fn test_watch_only_waits_for_first_change() {
+    let dir = Path::new("target/cue_watch_only_test");
+    fs::create_dir_all(dir).ok();
+    let mut child = cue()
+        .args([
+            "-w",
+            "target/cue_watch_only_test",
+            "-r",
+            "echo ran-on-change",
+            "--watch-only",
+            "--until",
+            "ran-on-change",
+            "--no-clear",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let mut before = String::new();
+    while !before.contains("run: echo ran-on-change") {
+        if reader.read_line(&mut before).unwrap_or(0) == 0 {
+            break;
+        }
+    }
+    fs::write(dir.join("changed.txt"), "x").ok();
+    let status = child.wait().expect("failed to run");
+    fs::remove_dir_all(dir).ok();
+
+    assert!(before.contains("ready — watching 1 path, the command runs on the first change"));
+    assert!(!before.contains("\nran-on-change"));
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupt_leaves_no_stray_processes() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cue"))
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo started && sleep 73.21 && echo done",
+            "--shell",
+            "--no-clear",
+        ])
+        // off a terminal, where each run gets a process group of its own
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    // wait for the command's own output, not cue's line announcing it; the
+    // reader stays open so cue can still print its summary
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let started = lines
+        .by_ref()
+        .map_while(Result::ok)
+        .any(|line| line == "started");
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("failed to run kill");
+    child.wait().expect("failed to wait");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+
+    let strays = Command::new("pgrep")
+        .args(["-f", "sleep 73.21"])
+        .output()
+        .expect("failed to run pgrep");
+    assert!(started);
+    assert!(stdout(&strays).trim().is_empty());
+}
+
+// runs cue until `--until saw` matches, making `change` once it's watching
+fn run_until_saw(args: &[&str], change: impl FnOnce()) -> (bool, String) {
+    let mut child = cue()
+        .args(args)
+        .args(["--shell", "--watch-only", "--until", "saw", "--no-clear"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let mut out = String::new();
+    while !out.contains("ready") {
+        if reader.read_line(&mut out).unwrap_or(0) == 0 {
+            break;
+        }
+    }
+    change();
+    // a change cue misses would otherwise leave it watching forever
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while child.try_wait().ok().flatten().is_none() && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    child.kill().ok();
+    let status = child.wait().expect("failed to run");
+    reader.read_to_string(&mut out).ok();
+    (status.success(), out)
+}
+
+#[test]
+fn test_skip_unchanged_ignores_first_resave() {
+    let project = Project::new();
+    project.write("src/same.txt", "same");
+    project.write("src/edited.txt", "old");
+    let src = project.path("src");
+    let (ok, out) = run_until_saw(
+        &[
+            "-w",
+            &src.display().to_string(),
+            "-r",
+            "echo saw {name}",
+            "--skip-unchanged",
+        ],
+        || {
+            project.write("src/same.txt", "same");
+            std::thread::sleep(std::time::Duration::from_millis(600));
+            project.write("src/edited.txt", "new");
+        },
+    );
+
+    assert!(ok);
+    assert!(out.contains("saw edited.txt"));
+    assert!(!out.contains("saw same.txt"));
+}
+
+#[test]
+fn test_atomic_save_onto_watched_file_counts_as_modify() {
+    let dir = Path::new("target/cue_atomic_save_test");
+    fs::create_dir_all(dir).ok();
+    fs::write(dir.join("real.txt"), "old").ok();
+    let (ok, out) = run_until_saw(
+        &[
+            "-w",
+            "target/cue_atomic_save_test/real.txt",
+            "-r",
+            "echo saw $CUE_EVENT_KIND $CUE_CHANGED_NAME",
+        ],
+        || {
+            fs::write(dir.join(".real.txt.tmp"), "new").ok();
+            fs::rename(dir.join(".real.txt.tmp"), dir.join("real.txt")).ok();
+        },
+    );
+    fs::remove_dir_all(dir).ok();
+
+    assert!(ok);
+    assert!(out.contains("saw modify real.txt"));
+}
+
+#[test]
+fn test_atomic_save_inside_watched_dir_triggers_with_real_file() {
+    let dir = Path::new("target/cue_atomic_dir_test");
+    fs::create_dir_all(dir).ok();
+    fs::write(dir.join("real.txt"), "old").ok();
+    let (ok, out) = run_until_saw(
+        &[
+            "-w",
+            "target/cue_atomic_dir_test",
+            "-r",
+            "echo saw $CUE_EVENT_KIND {file} [{files}]",
+        ],
+        || {
+            fs::write(dir.join("save_tmp_1"), "new").ok();
+            fs::rename(dir.join("save_tmp_1"), dir.join("real.txt")).ok();
+        },
+    );
+    fs::remove_dir_all(dir).ok();
+
+    assert!(ok);
+    assert!(out.contains("saw modify"));
+    assert!(out.contains("real.txt"));
+    assert!(!out.contains("save_tmp_1"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_placeholder_in_file_name_is_not_expanded() {
+    let project = Project::new();
+    let out = watch_for(
+        &project,
+        &["-r", "echo got {file}"],
+        std::time::Duration::from_millis(600),
+        || project.write("src/a;touch PWNED;#{dir}", "x"),
+    );
+
+    assert!(
+        out.lines()
+            .any(|l| l.starts_with("got ") && l.ends_with("/src/a;touch PWNED;#{dir}"))
+    );
+    assert!(!project.path("PWNED").exists());
+}
+
+#[test]
+fn test_rename_into_watched_dir_stays_a_rename() {
+    let dir = Path::new("target/cue_rename_into_test");
+    let outside = Path::new("target/cue_rename_into_outside.txt");
+    fs::create_dir_all(dir).ok();
+    fs::write(outside, "x").ok();
+    let (ok, out) = run_until_saw(
+        &[
+            "-w",
+            "target/cue_rename_into_test",
+            "-r",
+            "echo saw $CUE_EVENT_KIND $CUE_CHANGED_NAME",
+            "--events",
+            "rename",
+        ],
+        || {
+            fs::rename(outside, dir.join("moved.txt")).ok();
+        },
+    );
+    fs::remove_dir_all(dir).ok();
+
+    assert!(ok);
+    assert!(out.contains("saw rename moved.txt"));
+}
+
+#[test]
+fn test_fail_limit_resumes_on_enter() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cue"))
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "false",
+            "--fail-limit",
+            "1",
+            "--no-clear",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut stdin = child.stdin.take().unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .map_while(Result::ok);
+    let paused = lines
+        .by_ref()
+        .any(|line| line.contains("paused after 1 failures"));
+    writeln!(stdin).ok();
+    let resumed = lines.any(|line| line.contains("resumed — waiting for changes"));
+    child.kill().ok();
+    child.wait().ok();
+
+    assert!(paused);
+    assert!(resumed);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupt_summary_uses_label() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cue"))
+        .args([
+            "-w",
+            "src",
+            "-r",
+            "echo started",
+            "--label",
+            "session",
+            "--no-clear",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut lines = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .map_while(Result::ok);
+    let finished = lines.by_ref().any(|line| line.contains("exited 0"));
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("failed to run kill");
+    let rest: Vec<String> = lines.collect();
+    child.wait().expect("failed to wait");
+
+    assert!(finished);
+    assert!(
+        rest.iter()
+            .any(|line| line.starts_with("[cue:session] ") && line.contains(" passed"))
+    );
+}
+
+// util-linux's script gives cue a terminal for stdin, as in an interactive shell
+#[cfg(target_os = "linux")]
+#[test]
+fn test_command_can_use_the_terminal() {
+    let command = format!(
+        "{} -w src -r 'stty -echo && stty echo && echo ttyok' --shell --until ttyok --no-clear",
+        env!("CARGO_BIN_EXE_cue")
+    );
+    let mut child = Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run script");
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let mut status = None;
+    while status.is_none() && std::time::Instant::now() < deadline {
+        status = child.try_wait().expect("failed to wait");
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    if status.is_none() {
+        child.kill().ok();
+    }
+    let output = child.wait_with_output().expect("failed to wait");
+
+    assert!(status.is_some_and(|s| s.success()));
+    assert!(stdout(&output).contains("ttyok"));
+}
+
+// on a terminal the run shares cue's process group, so a restart has to find
+// what the shell started by itself
+#[cfg(target_os = "linux")]
+#[test]
+fn test_restart_on_terminal_stops_grandchildren() {
+    let dir = Path::new("target/cue_terminal_restart_test");
+    fs::create_dir_all(dir).ok();
+    let command = format!(
+        "{} -w {} -r 'sleep 74.31 & echo started; wait' --shell --no-clear",
+        env!("CARGO_BIN_EXE_cue"),
+        dir.display()
+    );
+    let mut child = Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run script");
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut wait_for_start = || {
+        lines
+            .by_ref()
+            .map_while(Result::ok)
+            .any(|line| line.trim_end() == "started")
+    };
+    let first = wait_for_start();
+    fs::write(dir.join("changed.txt"), "x").ok();
+    let second = wait_for_start();
+    let sleeps = Command::new("pgrep")
+        .args(["-f", "^sleep 74.31"])
+        .output()
+        .expect("failed to run pgrep");
+    Command::new("pkill")
+        .args(["-INT", "-f", "cue_terminal_restart_test -r"])
+        .status()
+        .expect("failed to run pkill");
+    child.wait().expect("failed to wait");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let strays = Command::new("pgrep")
+        .args(["-f", "^sleep 74.31"])
+        .output()
+        .expect("failed to run pgrep");
+    fs::remove_dir_all(dir).ok();
+
+    assert!(first && second);
+    assert_eq!(stdout(&sleeps).lines().count(), 1);
+    assert!(stdout(&strays).trim().is_empty());
+}
+
+// runs cue on the project's src, makes `changes` once it's watching and
+// returns everything it printed by `settle` after them
+#[cfg(unix)]
+fn watch_for(
+    project: &Project,
+    args: &[&str],
+    settle: std::time::Duration,
+    changes: impl FnOnce(),
+) -> String {
+    let mut child = project
+        .cue()
+        .args(["-w", "src"])
+        .args(args)
+        .args(["--shell", "--watch-only", "--no-clear"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let mut out = String::new();
+    while !out.contains("ready") {
+        if reader.read_line(&mut out).unwrap_or(0) == 0 {
+            break;
+        }
+    }
+    changes();
+    std::thread::sleep(settle);
+    Command::new("kill")
+        .arg(child.id().to_string())
+        .status()
+        .expect("failed to run kill");
+    child.wait().expect("failed to wait");
+    reader.read_to_string(&mut out).ok();
+    out
+}
+
+fn count_lines(out: &str, line: &str) -> usize {
+    out.lines().filter(|l| *l == line).count()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_debounce_runs_once_with_the_final_save() {
+    let project = Project::new();
+    let out = watch_for(
+        &project,
+        &["-r", "echo saved $(cat {file})", "-d", "300"],
+        std::time::Duration::from_millis(800),
+        || {
+            for i in 1..=5 {
+                project.write("src/a.txt", &format!("v{}", i));
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+        },
+    );
+
+    assert_eq!(out.matches("\nsaved ").count(), 1);
+    assert_eq!(count_lines(&out, "saved v5"), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_max_wait_runs_while_changes_keep_coming() {
+    let project = Project::new();
+    let out = watch_for(
+        &project,
+        &["-r", "echo tick", "-d", "400", "--max-wait", "300"],
+        std::time::Duration::from_millis(700),
+        || {
+            for i in 0..12 {
+                project.write("src/a.txt", &i.to_string());
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+        },
+    );
+
+    // without --max-wait the steady stream would settle into a single run
+    assert!(count_lines(&out, "tick") >= 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_debounce_per_path_runs_each_root() {
+    let project = Project::new();
+    fs::create_dir(project.path("other")).expect("failed to create dir");
+    let changes = || {
+        project.write("src/a.txt", "x");
+        std::thread::sleep(std::time::Duration::from_millis(250));
+        project.write("other/b.txt", "x");
+    };
+    let args = ["-w", "other", "-r", "echo ran {name}", "-d", "400"];
+    let shared = watch_for(
+        &project,
+        &args,
+        std::time::Duration::from_millis(900),
+        changes,
+    );
+    let per_path = watch_for(
+        &project,
+        &[&args[..], &["--debounce-per-path"]].concat(),
+        std::time::Duration::from_millis(900),
+        changes,
+    );
+
+    assert_eq!(shared.matches("\nran ").count(), 1);
+    assert_eq!(count_lines(&per_path, "ran a.txt"), 1);
+    assert_eq!(count_lines(&per_path, "ran b.txt"), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_queue_reruns_after_the_current_run() {
+    let project = Project::new();
+    let out = watch_for(
+        &project,
+        &[
+            "-r",
+            "echo start {name}; sleep 0.6; echo end",
+            "-d",
+            "50",
+            "--queue",
+        ],
+        std::time::Duration::from_millis(1800),
+        || {
+            project.write("src/a.txt", "x");
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            project.write("src/b.txt", "x");
+        },
+    );
+
+    assert!(out.contains("change queued"));
+    assert_eq!(count_lines(&out, "end"), 2);
+    let first = out.find("start a.txt").unwrap();
+    assert!(out.find("start b.txt").is_some_and(|second| first < second));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_change_during_run_restarts_it() {
+    let project = Project::new();
+    let out = watch_for(
+        &project,
+        &["-r", "echo start {name}; sleep 0.6; echo end", "-d", "50"],
+        std::time::Duration::from_millis(1200),
+        || {
+            project.write("src/a.txt", "x");
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            project.write("src/b.txt", "x");
+        },
+    );
+
+    assert_eq!(count_lines(&out, "start a.txt"), 1);
+    assert_eq!(count_lines(&out, "start b.txt"), 1);
+    assert_eq!(count_lines(&out, "end"), 1);
+}
+
+// the handler sleeps before it prints, which a run killed outright never would
+#[cfg(unix)]
+const TRAPS_TERM: &str = "trap 'sleep 0.3; echo cleaned up; exit 0' TERM; echo started {name}; while true; do sleep 0.05; done";
+
+#[cfg(unix)]
+#[test]
+fn test_restart_lets_the_command_clean_up() {
+    let project = Project::new();
+    let out = watch_for(
+        &project,
+        &["-r", TRAPS_TERM, "-d", "50", "--kill-timeout", "3000"],
+        std::time::Duration::from_millis(1200),
+        || {
+            project.write("src/a.txt", "x");
+            std::thread::sleep(std::time::Duration::from_millis(400));
+            project.write("src/b.txt", "x");
+        },
+    );
+
+    let cleaned = out.find("\ncleaned up\n");
+    let restarted = out.find("\nstarted b.txt\n");
+    assert!(cleaned.is_some());
+    assert!(cleaned < restarted);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ctrl_c_lets_the_command_clean_up() {
+    let project = Project::new();
+    let mut child = project
+        .cue()
+        .args(["-w", "src", "-r", TRAPS_TERM, "--shell", "--no-clear"])
+        .args(["--kill-timeout", "3000"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run");
+    let mut lines = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .map_while(Result::ok);
+    let started = lines.by_ref().any(|line| line.starts_with("started"));
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("failed to run kill");
+    let rest: Vec<String> = lines.collect();
+    child.wait().expect("failed to wait");
+
+    assert!(started);
+    assert!(rest.iter().any(|line| line == "cleaned up"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_editor_swap_files_ignored_by_default() {
+    let save = |project: &Project| {
+        project.write("src/.a.txt.swp", "x");
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        project.write("src/a.txt", "x");
+    };
+    let project = Project::new();
+    let ignored = watch_for(
+        &project,
+        &["-r", "echo ran {name}", "-d", "50"],
+        std::time::Duration::from_millis(400),
+        || save(&project),
+    );
+    let project = Project::new();
+    let included = watch_for(
+        &project,
+        &["-r", "echo ran {name}", "-d", "50", "--no-default-ignores"],
+        std::time::Duration::from_millis(400),
+        || save(&project),
+    );
+
+    assert_eq!(count_lines(&ignored, "ran .a.txt.swp"), 0);
+    assert_eq!(count_lines(&ignored, "ran a.txt"), 1);
+    assert_eq!(count_lines(&included, "ran .a.txt.swp"), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_retry_reruns_a_failed_command() {
+    let project = Project::new();
+    let out = watch_for(
+        &project,
+        &["-r", "echo attempt; false", "--retry", "2"],
+        std::time::Duration::from_millis(2500),
+        || project.write("src/a.txt", "x"),
+    );
+
+    assert_eq!(count_lines(&out, "attempt"), 3);
+    assert!(out.contains("retrying in 500ms (1/2)"));
+    assert!(out.contains("retrying in 1.0s (2/2)"));
+}